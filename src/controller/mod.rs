@@ -42,9 +42,18 @@ The Controller has a central EventHandler, which handles discord events the serv
 #![allow(dead_code, unused_imports, unused_variables)]
 
 use crate::core::base::{Choice, ID};
-use crate::core::interface::{CommandTx, EventRx, Interface};
-
+use crate::core::clock;
+use crate::core::interface::{Action, CommandTx, Event, EventRx, Interface};
+use crate::core::rolegen;
+use crate::core::roles::{Role, Team};
+use crate::core::rules::Rules;
+use crate::core::storage::GameStore;
+use crate::core::Core;
+
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::task::JoinHandle;
 
 use serenity;
 use serenity::all::{CommandDataOptionValue, CommandInteraction, CreateButton, User};
@@ -65,6 +74,8 @@ impl ID for UserId {}
 
 pub type GameId = u64;
 
+impl ID for GameId {}
+
 #[derive(Clone, Debug)]
 pub struct GameInitializer {
     pub game_id: GameId,
@@ -101,12 +112,29 @@ impl GameInitializer {
 pub struct GameData {
     pub game_id: GameId,
     pub cmd_tx: CommandTx<UserId>,
-    pub event_rx: EventRx<UserId>,
+    pub channels: GameChannels,
+    pub core_join: JoinHandle<()>,
+    pub events_join: JoinHandle<()>, // Posts the core's events to the game's channels
+    pub targeting_threads: HashMap<ChannelId, UserId>,
+    pub reveal_threads: HashMap<ChannelId, UserId>,
+}
+
+// Where a game is played. Saved next to the game's snapshot, so a restored game can find them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameChannels {
     pub initializer_thread_id: ChannelId,
     pub main_channel_id: ChannelId,
     pub mafia_thread_id: ChannelId,
-    pub targeting_threads: HashMap<ChannelId, UserId>,
-    pub reveal_threads: HashMap<ChannelId, UserId>,
+    pub masons_thread_id: Option<ChannelId>, // Only for games with masons
+    #[serde(default)]
+    pub faction_threads: HashMap<Team, ChannelId>, // Factions other than the mafia
+}
+
+// Who gets to see an event
+enum Audience {
+    Everyone,
+    Faction(Team),
+    Players(Vec<UserId>),
 }
 
 pub struct Lobby {
@@ -118,6 +146,8 @@ pub struct Controller {
     pub guild_id: GuildId,
     pub lobbies: HashMap<ChannelId, Lobby>,
     pub games: HashMap<GameId, GameData>,
+    pub store: GameStore,
+    pub next_game_id: GameId,
 }
 
 pub enum ButtonAction {
@@ -128,11 +158,295 @@ pub enum ButtonAction {
 }
 
 impl Controller {
-    pub fn new(guild_id: GuildId, http: Arc<Http>) -> Self {
+    pub fn new(guild_id: GuildId, http: Arc<Http>, store: GameStore) -> Self {
         Self {
             guild_id,
             lobbies: HashMap::new(),
             games: HashMap::new(),
+            store,
+            next_game_id: 0,
+        }
+    }
+
+    // Pick up the games that were running when the bot last stopped
+    pub async fn restore_games(&mut self, http: Arc<Http>) {
+        let restored = match self
+            .store
+            .restore_all::<UserId, GameId>(clock::system())
+            .await
+        {
+            Ok(restored) => restored,
+            Err(why) => {
+                println!("Error restoring games: {why:?}");
+                return;
+            }
+        };
+        for game in restored {
+            self.next_game_id = self.next_game_id.max(game.game_id + 1);
+            let channels = match Self::load_channels(&self.store, game.game_id).await {
+                Ok(channels) => channels,
+                Err(why) => {
+                    // Nowhere to play it, so don't leave it waiting on its events
+                    println!("No channels for game {}: {why}", game.game_id);
+                    Interface::send_close(&game.cmd_tx).await;
+                    continue;
+                }
+            };
+            self.add_game(
+                http.clone(),
+                game.game_id,
+                game.cmd_tx,
+                game.event_rx,
+                game.join,
+                channels,
+            );
+        }
+        println!("Restored {} games in {}", self.games.len(), self.guild_id);
+    }
+
+    fn channels_path(store: &GameStore, game_id: GameId) -> PathBuf {
+        store.dir.join(format!("{}.channels.toml", game_id))
+    }
+
+    async fn save_channels(
+        store: &GameStore,
+        game_id: GameId,
+        channels: &GameChannels,
+    ) -> Result<(), String> {
+        let toml_str = toml::to_string(channels).map_err(|e| e.to_string())?;
+        tokio::fs::create_dir_all(&store.dir)
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::fs::write(Self::channels_path(store, game_id), toml_str)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn load_channels(store: &GameStore, game_id: GameId) -> Result<GameChannels, String> {
+        let toml_str = tokio::fs::read_to_string(Self::channels_path(store, game_id))
+            .await
+            .map_err(|e| e.to_string())?;
+        toml::from_str(&toml_str).map_err(|e| e.to_string())
+    }
+
+    // Track a running core, and start posting its events
+    fn add_game(
+        &mut self,
+        http: Arc<Http>,
+        game_id: GameId,
+        cmd_tx: CommandTx<UserId>,
+        event_rx: EventRx<UserId>,
+        core_join: JoinHandle<()>,
+        channels: GameChannels,
+    ) {
+        let channels_path = Self::channels_path(&self.store, game_id);
+        let events_join = tokio::spawn(Self::post_events(
+            http,
+            channels.clone(),
+            event_rx,
+            channels_path,
+        ));
+        let game = GameData {
+            game_id,
+            cmd_tx,
+            channels,
+            core_join,
+            events_join,
+            targeting_threads: HashMap::new(),
+            reveal_threads: HashMap::new(),
+        };
+        self.games.insert(game_id, game);
+    }
+
+    fn audience(event: &Event<UserId>) -> Audience {
+        match event {
+            Event::StartRole { player, .. }
+            | Event::PrivateSave { mark: player, .. }
+            | Event::ScriptMessage { player, .. } => Audience::Players(vec![*player]),
+            Event::Target { actor, .. }
+            | Event::Block { actor, .. }
+            | Event::Save { actor, .. }
+            | Event::Investigate { actor, .. }
+            | Event::Track { actor, .. }
+            | Event::Watch { actor, .. } => Audience::Players(vec![*actor]),
+            Event::Steal { actor, target } => Audience::Players(vec![*actor, *target]),
+            Event::Scheme { team, .. }
+            | Event::SchemePicks { team, .. }
+            | Event::Designate { team, .. } => Audience::Faction(*team),
+            _ => Audience::Everyone,
+        }
+    }
+
+    // Post a game's events until its core closes. Private events only go to who they're for
+    async fn post_events(
+        http: Arc<Http>,
+        channels: GameChannels,
+        mut event_rx: EventRx<UserId>,
+        channels_path: PathBuf,
+    ) {
+        while let Some(event) = event_rx.recv().await {
+            let content = format!("{:?}", event);
+            let result = match Self::audience(&event) {
+                Audience::Everyone => channels.main_channel_id.say(&http, content).await.map(drop),
+                Audience::Faction(team) => {
+                    let thread_id = match team {
+                        Team::Mafia => Some(channels.mafia_thread_id),
+                        team => channels.faction_threads.get(&team).copied(),
+                    };
+                    match thread_id {
+                        Some(thread_id) => thread_id.say(&http, content).await.map(drop),
+                        None => Ok(()),
+                    }
+                }
+                Audience::Players(players) => {
+                    let mut result = Ok(());
+                    for player in players {
+                        let sent = match player.create_dm_channel(&http).await {
+                            Ok(dm) => dm.say(&http, content.clone()).await.map(drop),
+                            Err(why) => Err(why),
+                        };
+                        if let Err(why) = sent {
+                            result = Err(why);
+                        }
+                    }
+                    result
+                }
+            };
+            if let Err(why) = result {
+                println!("Cannot post game event: {why}");
+            }
+            if let Event::End { .. } = event {
+                // The core drops its snapshot once the game is over
+                let _ = tokio::fs::remove_file(&channels_path).await;
+            }
+        }
+    }
+
+    // Sorted players whose role matches
+    fn players_where(
+        roles: &HashMap<UserId, Role<UserId>>,
+        matches: impl Fn(&Role<UserId>) -> bool,
+    ) -> Vec<UserId> {
+        let mut players: Vec<UserId> = roles
+            .iter()
+            .filter(|(_, role)| matches(role))
+            .map(|(player, _)| *player)
+            .collect();
+        players.sort();
+        players
+    }
+
+    // Open a private thread in the channel with just the given members
+    async fn open_private_thread(
+        http: &Arc<Http>,
+        channel_id: ChannelId,
+        name: String,
+        members: &[UserId],
+    ) -> ChannelId {
+        let create_thread = channel_id.create_thread(
+            http,
+            CreateThread::new(name).kind(channel::ChannelType::PrivateThread),
+        );
+        let thread = create_thread.await.expect("Cannot create thread");
+        for &member in members {
+            if let Err(why) = thread.id.add_thread_member(http, member).await {
+                println!("Cannot add member to thread: {why}");
+            }
+        }
+        thread.id
+    }
+
+    // Open a private thread for the masons, next to the mafia thread. None without masons
+    async fn open_masons_thread(
+        http: &Arc<Http>,
+        channel_id: ChannelId,
+        game_id: GameId,
+        roles: &HashMap<UserId, Role<UserId>>,
+    ) -> Option<ChannelId> {
+        let masons = Self::players_where(roles, |role| *role == Role::MASON);
+        if masons.is_empty() {
+            return None;
+        }
+        let name = format!("Game # {} Masons", game_id);
+        Some(Self::open_private_thread(http, channel_id, name, &masons).await)
+    }
+
+    // Roll the roles for the lobby's players, open the game's threads and start the core
+    async fn start_game(&mut self, ctx: &Context, lobby_channel_id: ChannelId) {
+        let Some(lobby) = self.lobbies.get_mut(&lobby_channel_id) else {
+            println!("Lobby not found");
+            return;
+        };
+        let Some(game_init) = lobby.game_initializer.take() else {
+            println!("Game initializer not found");
+            return;
+        };
+        let game_id = game_init.game_id;
+        let rules = Rules::default();
+        let rolegen_rules = &rules.rolegen_rules;
+        let roles = match rolegen::generate(
+            &game_init.players,
+            rolegen_rules,
+            &rules.role_definitions,
+            None,
+        ) {
+            Ok(roles) => roles,
+            Err(why) => {
+                let content = format!("Cannot start the game: {why:?}");
+                if let Err(why) = game_init.thread_id.say(&ctx.http, content).await {
+                    println!("Cannot send message: {why}");
+                }
+                lobby.game_initializer = Some(game_init);
+                return;
+            }
+        };
+
+        let create_thread = lobby_channel_id.create_thread(
+            &ctx.http,
+            CreateThread::new(format!("Game # {}", game_id))
+                .kind(channel::ChannelType::PublicThread),
+        );
+        let main_thread = create_thread.await.expect("Cannot create thread");
+        let mafia = Self::players_where(&roles, |role| role.team() == Team::Mafia);
+        let name = format!("Game # {} Mafia", game_id);
+        let mafia_thread_id =
+            Self::open_private_thread(&ctx.http, lobby_channel_id, name, &mafia).await;
+        let masons_thread_id =
+            Self::open_masons_thread(&ctx.http, lobby_channel_id, game_id, &roles).await;
+        let mut faction_threads = HashMap::new();
+        for team in [Team::Family, Team::Killer] {
+            let members = Self::players_where(&roles, |role| role.team() == team);
+            if members.is_empty() {
+                continue;
+            }
+            let name = format!("Game # {} {:?}", game_id, team);
+            let thread_id =
+                Self::open_private_thread(&ctx.http, lobby_channel_id, name, &members).await;
+            faction_threads.insert(team, thread_id);
+        }
+        let channels = GameChannels {
+            initializer_thread_id: game_init.thread_id,
+            main_channel_id: main_thread.id,
+            mafia_thread_id,
+            masons_thread_id,
+            faction_threads,
+        };
+        if let Err(why) = Self::save_channels(&self.store, game_id, &channels).await {
+            println!("Cannot save channels for game {}: {why}", game_id);
+        }
+
+        let (core, event_rx, cmd_tx) = Core::new(game_id, roles, rules);
+        let core_join = core.with_storage(self.store.clone()).spawn().await;
+        self.add_game(
+            ctx.http.clone(),
+            game_id,
+            cmd_tx.clone(),
+            event_rx,
+            core_join,
+            channels,
+        );
+        if let Err(why) = Interface::send_action(&cmd_tx, Action::Start).await {
+            println!("Cannot start game {}: {:?}", game_id, why);
         }
     }
 
@@ -297,7 +611,8 @@ impl Controller {
             return;
         }
 
-        let game_id = 0; // TODO
+        let game_id = self.next_game_id;
+        self.next_game_id += 1;
 
         // Send a message to the lobby channel:
         let msg = lobby_channel_id
@@ -444,7 +759,11 @@ impl Controller {
                         // Watch the game
                     }
                     Some(ButtonAction::StartGame(lobby_channel_id, user_id)) => {
-                        // Start the game
+                        self.start_game(&ctx, lobby_channel_id).await;
+                        let builder = CreateInteractionResponse::Acknowledge;
+                        if let Err(why) = component.create_response(&ctx.http, builder).await {
+                            println!("Cannot respond to button press: {why}");
+                        }
                     }
                     Some(ButtonAction::Reveal(game_id, user_id)) => {
                         // Reveal in the game
@@ -4,7 +4,7 @@ use std::hash::Hash;
 use serde::{Deserialize, Serialize};
 
 pub trait ID:
-    Eq + Hash + Copy + Debug + Display + Default + Sync + Send + Serialize + 'static
+    Eq + Ord + Hash + Copy + Debug + Display + Default + Sync + Send + Serialize + 'static
 {
 }
 
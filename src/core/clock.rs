@@ -0,0 +1,73 @@
+// Clocks used by the core for timers. The system clock follows wall time,
+//   the manual clock only moves when told to, for tests and replays.
+
+use chrono::{DateTime, Local};
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::time::Duration;
+
+pub type ClockRef = Arc<dyn Clock>;
+
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> DateTime<Local>;
+
+    // Resolves once the clock reaches end_time
+    fn sleep_until(&self, end_time: DateTime<Local>) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+pub fn system() -> ClockRef {
+    Arc::new(SystemClock)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn sleep_until(&self, end_time: DateTime<Local>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let duration = (end_time - self.now()).to_std().unwrap_or(Duration::ZERO);
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+// Virtual time. Sleepers wake when the clock is advanced past their end time.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: watch::Sender<DateTime<Local>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Local>) -> Arc<Self> {
+        let (now, _) = watch::channel(start);
+        Arc::new(ManualClock { now })
+    }
+
+    pub fn set(&self, time: DateTime<Local>) {
+        self.now.send_replace(time);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let duration = chrono::Duration::from_std(duration).expect("Duration out of range");
+        self.now.send_modify(|now| *now += duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.now.borrow()
+    }
+
+    fn sleep_until(&self, end_time: DateTime<Local>) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let mut rx = self.now.subscribe();
+        Box::pin(async move {
+            // The sender lives as long as the clock, which outlives anything sleeping on it
+            let _ = rx.wait_for(|&now| now >= end_time).await;
+        })
+    }
+}
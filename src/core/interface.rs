@@ -1,11 +1,14 @@
 use crate::base::{Choice, ID};
+use crate::clock;
 use crate::core::{Core, PhaseKind, State};
-use crate::roles::{Role, RoleKind, Team};
+use crate::log::ActionLog;
+use crate::roles::{Modifier, Role, RoleInfo, RoleKind, Team};
 use crate::rules::Rules;
+use crate::stats::Stats;
 
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
 use std::num::ParseIntError;
@@ -84,6 +87,30 @@ impl<PID: Debug + Eq + Hash> Interface<PID> {
         resp
     }
 
+    pub async fn send_stats(cmd_tx: &CommandTx<PID>) -> Result<Stats<PID>, CoreError<PID>> {
+        let (tx, rx) = oneshot::channel();
+        cmd_tx.send(Command::Stats(tx)).await.unwrap();
+        let resp = rx.await.unwrap();
+        resp
+    }
+
+    pub async fn send_view(
+        cmd_tx: &CommandTx<PID>,
+        player: PID,
+    ) -> Result<PlayerView<PID>, CoreError<PID>> {
+        let (tx, rx) = oneshot::channel();
+        cmd_tx.send(Command::View(player, tx)).await.unwrap();
+        let resp = rx.await.unwrap();
+        resp
+    }
+
+    pub async fn send_log(cmd_tx: &CommandTx<PID>) -> Result<ActionLog<PID>, CoreError<PID>> {
+        let (tx, rx) = oneshot::channel();
+        cmd_tx.send(Command::Log(tx)).await.unwrap();
+        let resp = rx.await.unwrap();
+        resp
+    }
+
     pub async fn send_serialize(
         cmd_tx: &CommandTx<PID>,
     ) -> Result<SerializedGame, SerializeGameError> {
@@ -103,11 +130,26 @@ impl<PID: Debug + Eq + Hash> Default for Interface<PID> {
     }
 }
 
-#[derive(Debug)]
+// What a single player can see of the game
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerView<PID> {
+    pub player: PID,
+    pub day_no: u32,
+    pub phase: PhaseKind,
+    pub role: Role<PID>, // As the player knows it
+    pub modifiers: Vec<Modifier>,
+    pub charges: Option<u8>, // None is no limit
+    pub teammates: Vec<PID>,
+    pub players: Vec<PID>, // Living players
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SerializedGame {
     pub game_id: String, // Stringified id?
     pub state: String,   // json string
     pub rules: String,   // toml string
+    pub log: String,     // json string
+    pub saved_at: chrono::DateTime<chrono::Local>,
 }
 
 impl<PID: ID, GID: Display> TryFrom<&Core<PID, GID>> for SerializedGame {
@@ -117,10 +159,13 @@ impl<PID: ID, GID: Display> TryFrom<&Core<PID, GID>> for SerializedGame {
         let id = core.game_id.to_string();
         let state = serde_json::to_string(&core.state)?;
         let rules = toml::to_string(&core.rules)?;
+        let log = serde_json::to_string(&core.log)?;
         Ok(SerializedGame {
             game_id: id,
             state,
             rules,
+            log,
+            saved_at: core.clock.now(),
         })
     }
 }
@@ -134,11 +179,16 @@ impl<'a, PID: Debug + Eq + Hash + Deserialize<'a>, GID: FromStr<Err = ParseIntEr
         let game_id: GID = GID::from_str(self.game_id.as_str())?;
         let state = serde_json::from_str(self.state.as_str())?;
         let rules = toml::from_str(&self.rules)?;
+        let log = serde_json::from_str(self.log.as_str())?;
         Ok(Core {
             game_id,
             state,
             rules,
             inter: Interface::new(),
+            storage: None,
+            log,
+            clock: clock::system(),
+            action_time: chrono::Local::now(),
         })
     }
 }
@@ -161,6 +211,7 @@ impl From<toml::ser::Error> for SerializeGameError {
     }
 }
 
+#[derive(Debug)]
 pub enum DeserializeGameError {
     ParseIntError(std::num::ParseIntError),
     JsonError(serde_json::Error),
@@ -188,6 +239,9 @@ impl From<toml::de::Error> for DeserializeGameError {
 pub type ActionResponder<PID> = oneshot::Sender<Result<(), CoreError<PID>>>;
 pub type StateResponder<PID> = oneshot::Sender<Result<State<PID>, CoreError<PID>>>;
 pub type RulesResponser<PID> = oneshot::Sender<Result<Rules, CoreError<PID>>>;
+pub type StatsResponder<PID> = oneshot::Sender<Result<Stats<PID>, CoreError<PID>>>;
+pub type ViewResponder<PID> = oneshot::Sender<Result<PlayerView<PID>, CoreError<PID>>>;
+pub type LogResponder<PID> = oneshot::Sender<Result<ActionLog<PID>, CoreError<PID>>>;
 pub type SerializeResponder = oneshot::Sender<Result<SerializedGame, SerializeGameError>>;
 
 // Responses are either () for Action or status for Status?
@@ -196,6 +250,9 @@ pub enum Command<PID: Eq + Hash> {
     Action(Action<PID>, ActionResponder<PID>),
     State(StateResponder<PID>),
     Rules(RulesResponser<PID>),
+    Stats(StatsResponder<PID>),
+    View(PID, ViewResponder<PID>),
+    Log(LogResponder<PID>),
     Serialize(SerializeResponder),
     Close,
 }
@@ -208,15 +265,25 @@ pub enum Action<PID: Eq + Hash> {
     Reveal { player: PID },
     Target { actor: PID, target: Choice<PID> },
     Scheme { actor: PID, mark: Choice<PID> },
+    Designate { actor: PID, killer: PID },
     Avenge { avenger: PID, victim: Choice<PID> },
     Elect { candidate: Choice<PID>, hammer: PID },
     Dawn,
+    Deadline,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Event<PID: Eq + Hash> {
     Start {
-        players: HashMap<PID, Role<PID>>,
+        players: Vec<PID>,
+        disclosure: StartDisclosure,
+    },
+    // Private to player. teammates is only filled in for kill factions and masons
+    StartRole {
+        player: PID,
+        role: Role<PID>,
+        modifiers: Vec<Modifier>,
+        teammates: Vec<PID>,
     },
     Vote {
         voter: PID,
@@ -231,17 +298,29 @@ pub enum Event<PID: Eq + Hash> {
         actor: PID,
         target: Choice<PID>,
     },
+    // A faction settled on its kill, with actor as the killer
     Scheme {
+        team: Team,
         actor: PID,
         mark: Choice<PID>,
     },
+    // Private to the faction. Every member's current pick, oldest first
+    SchemePicks {
+        team: Team,
+        picks: Vec<(PID, Choice<PID>)>,
+    },
+    // Private to the faction. The member who decides its kill under designated decisions
+    Designate {
+        team: Team,
+        killer: PID,
+    },
     Avenge {
         avenger: PID,
         target: PID,
     },
     Eliminate {
         player: PID,
-        role: Role<PID>,
+        role: RoleInfo<PID>,
     },
     Refocus {
         player: PID,
@@ -256,7 +335,7 @@ pub enum Event<PID: Eq + Hash> {
     Election {
         candidate: Choice<PID>,
         hammer: PID,
-        voters: Vec<PID>,
+        voters: Vec<(PID, i32)>, // Each voter with the weight of their vote
     },
     Block {
         actor: PID,
@@ -264,24 +343,63 @@ pub enum Event<PID: Eq + Hash> {
     },
     EvidentBlock {
         blocked: PID,
-        blockers: Vec<PID>,
+        blockers: Vec<RoleInfo<PID>>,
     },
     Save {
         actor: PID,
         target: PID,
     },
+    // A save only the saved player learns about
+    PrivateSave {
+        savior: RoleInfo<PID>,
+        mark: PID,
+    },
     EvidentSave {
-        savior: PID,
+        savior: RoleInfo<PID>,
+        mark: PID,
+    },
+    // The mark of a kill survived it
+    EvidentBulletproof {
         mark: PID,
     },
     Investigate {
         actor: PID,
         target: PID,
+        result: RoleInfo<PID>,
+    },
+    // Private to the thief and their target
+    Steal {
+        actor: PID,
+        target: PID,
+    },
+    Track {
+        actor: PID,
+        target: PID,
+        visited: Vec<PID>,
+    },
+    Watch {
+        actor: PID,
+        target: PID,
+        visitors: Vec<PID>,
     },
     Kill {
         killer: PID,
         mark: PID,
     },
+    Shoot {
+        actor: PID,
+        mark: PID,
+    },
+    // Private to player, sent by a custom role's script
+    ScriptMessage {
+        player: PID,
+        text: String,
+    },
+    // A custom role's script failed, so its action did nothing
+    ScriptFailed {
+        actor: PID,
+        error: String,
+    },
     NoNightKill,
     Day {
         day_no: u32,
@@ -295,14 +413,30 @@ pub enum Event<PID: Eq + Hash> {
         options: Vec<PID>,
     },
     Dawn,
+    Deadline {
+        phase: PhaseKind,
+        end_time: chrono::DateTime<chrono::Local>,
+    },
+    DeadlinePassed {
+        phase: PhaseKind,
+    },
     End {
-        winner: Team,
+        winner: Option<Team>, // None for a draw
+        rogue_winners: Vec<PID>,
         alive: Vec<PID>,
-        role_history: HashMap<PID, Vec<Role<PID>>>,
+        stats: Box<Stats<PID>>,
     },
     Close,
 }
 
+// Public information about the setup, as allowed by the start_roles rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartDisclosure {
+    Roles(BTreeMap<RoleKind, usize>),
+    Teams(BTreeMap<Team, usize>),
+    Mafia { mafia: usize, non_mafia: usize },
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CoreError<PID: Eq + Hash> {
     InvalidPhase {
@@ -318,8 +452,8 @@ pub enum CoreError<PID: Eq + Hash> {
     ExpectedSchemingRole {
         role: RoleKind,
     },
-    ExpectedCeleb {
-        actual: RoleKind,
+    ExpectedRevealingRole {
+        role: RoleKind,
     },
     ExpectedElection {
         candidate: Choice<PID>,
@@ -335,6 +469,13 @@ pub enum CoreError<PID: Eq + Hash> {
         actual: PID,
         options: Vec<PID>,
     },
+    IllegalSaveTarget {
+        actor: PID,
+        target: PID,
+    },
+    NoChargesLeft {
+        actor: PID,
+    },
     EventSendError(mpsc::error::SendError<Event<PID>>),
     Close,
 }
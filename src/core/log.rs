@@ -0,0 +1,77 @@
+// Action log. Every action a core accepts is recorded with the time it was handled,
+//   so the game can be replayed from its initial state.
+
+use crate::base::ID;
+use crate::clock::ManualClock;
+use crate::core::{Core, State};
+use crate::interface::{Action, CoreError, Event};
+use crate::rules::Rules;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionLog<PID: Eq + Hash> {
+    pub initial_state: State<PID>,
+    pub rules: Rules,
+    pub entries: Vec<LoggedAction<PID>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedAction<PID: Eq + Hash> {
+    pub time: chrono::DateTime<chrono::Local>,
+    pub action: Action<PID>,
+    pub from_timer: bool, // Elect, Dawn and Deadline fired by a timer
+}
+
+impl<PID: ID> ActionLog<PID> {
+    pub fn new(initial_state: State<PID>, rules: Rules) -> Self {
+        Self {
+            initial_state,
+            rules,
+            entries: Vec::new(),
+        }
+    }
+
+    // Replay every logged action into a fresh core, on a clock set to the time each
+    //   action was originally handled.
+    // Returns the final state and every event the core sent along the way.
+    pub async fn replay<GID: ID>(
+        &self,
+        game_id: GID,
+    ) -> Result<(State<PID>, Vec<Event<PID>>), CoreError<PID>> {
+        let (core, mut event_rx, _cmd_tx) = Core::new(game_id, HashMap::new(), self.rules.clone());
+        let start = self
+            .entries
+            .first()
+            .map_or_else(chrono::Local::now, |e| e.time);
+        let clock = ManualClock::new(start);
+        let mut core = core.with_clock(clock.clone());
+        core.state = self.initial_state.clone();
+
+        let collector = tokio::spawn(async move {
+            let mut events = Vec::new();
+            while let Some(event) = event_rx.recv().await {
+                events.push(event);
+            }
+            events
+        });
+
+        for entry in &self.entries {
+            clock.set(entry.time);
+            core.action_time = entry.time;
+            if entry.from_timer {
+                // The live core took the timer off the state before handling it
+                core.take_next_timer();
+            }
+            core.handle_action(entry.action.clone()).await?;
+        }
+
+        let state = core.state.clone();
+        // Dropping the core closes the event channel, ending the collector
+        drop(core);
+        let events = collector.await.expect("Replay event collector panicked");
+        Ok((state, events))
+    }
+}
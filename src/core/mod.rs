@@ -3,58 +3,72 @@
 // #![allow(dead_code, unused_variables, unused_imports)]
 
 pub mod base;
+pub mod clock;
 pub mod interface;
+pub mod log;
+pub mod roledefs;
+pub mod rolegen;
 pub mod roles;
 pub mod rules;
+pub mod scripting;
+pub mod stats;
+pub mod storage;
 pub mod test;
 pub mod timer;
 
 use base::{Choice, ID};
+use clock::ClockRef;
 use interface::{
-    Action, Command, CommandTx, CoreError, Event, EventRx, Interface, SerializeGameError,
-    SerializedGame,
+    Action, Command, CommandTx, CoreError, DeserializeGameError, Event, EventRx, Interface,
+    PlayerView, SerializeGameError, SerializedGame, StartDisclosure,
 };
-use roles::{DawnState, DawnStateChange, NightAction, Role, RoleKind, Team};
-use rules::Rules;
+use log::{ActionLog, LoggedAction};
+use roles::{
+    ActionType, DawnState, DawnStateChange, Modifier, NightAction, Role, RoleInfo, RoleKind, Team,
+    MAYOR_VOTES,
+};
+use rules::{
+    DayDeadline, InfoLevel, NightRules, Rules, SchemeDecision, SchemeTieBreak, StartRoles,
+};
+use stats::{
+    BlockRecord, ElectionRecord, KillRecord, NightOutcome, NightRecord, RoleRecord, SaveRecord,
+    Stats, VoteRecord,
+};
+use storage::GameStore;
 use timer::Timer;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
 use std::fmt::Debug;
+use std::future::Future;
 use std::hash::Hash;
+use std::num::ParseIntError;
+use std::pin::Pin;
+use std::str::FromStr;
 use toml;
 
 use tokio;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use tokio::time::Duration;
 
-// Maintains historical data about the game
-// Used for revealing information about the game
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Stats<PID: Eq + Hash> {
-    role_history: HashMap<PID, Vec<Role<PID>>>,
-}
-
-impl<PID: ID> Stats<PID> {
-    fn new() -> Self {
-        Stats {
-            role_history: HashMap::new(),
-        }
-    }
-}
-
-#[derive(EnumKind, Debug, Clone, Serialize, Deserialize)]
+#[derive(EnumKind, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[enum_kind(PhaseKind, derive(Serialize, Deserialize))]
 pub enum Phase<PID: Eq + Hash> {
     Init,
     Day {
         votes: HashMap<PID, Choice<PID>>, // voter -> choice
         blocks: HashMap<PID, Vec<PID>>,   // blocked -> blockers
+        #[serde(default = "HashMap::new")]
+        stolen: HashMap<PID, PID>, // victim -> thief
     },
     Night {
-        targets: HashMap<PID, Choice<PID>>, // actor -> target
-        scheme: Option<(PID, Choice<PID>)>, // actor -> (target, choice)
+        targets: HashMap<PID, Choice<PID>>,         // actor -> target
+        schemes: HashMap<Team, (PID, Choice<PID>)>, // faction -> (killer, mark), once settled
+        #[serde(default = "HashMap::new")]
+        picks: HashMap<Team, Vec<(PID, Choice<PID>)>>, // faction -> (member, mark), oldest first
     },
     Eclipse {
         avenger: PID,
@@ -62,7 +76,8 @@ pub enum Phase<PID: Eq + Hash> {
         options: Vec<PID>,
     },
     End {
-        winner: Team,
+        winner: Option<Team>, // None for a draw
+        rogue_winners: Vec<PID>,
     },
 }
 
@@ -72,30 +87,59 @@ impl<PID: ID> Phase<PID> {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct State<PID: Eq + Hash> {
     pub day_no: u32,
     pub players: HashMap<PID, Role<PID>>,
+    #[serde(default = "HashMap::new")]
+    pub modifiers: HashMap<PID, Vec<Modifier>>,
+    // Night action uses left. Players without an entry have no limit
+    #[serde(default = "HashMap::new")]
+    pub charges: HashMap<PID, u8>,
+    // Votes each player casts. Players without an entry cast one
+    #[serde(default = "HashMap::new")]
+    pub vote_weights: HashMap<PID, i32>,
+    // The member each faction designated to decide its kill
+    #[serde(default = "HashMap::new")]
+    pub designated: HashMap<Team, PID>,
     pub phase: Phase<PID>,
     pub timer: Option<Timer<PID>>,
-    pub role_history: HashMap<PID, Vec<Role<PID>>>,
+    pub deadline: Option<Timer<PID>>,
+    #[serde(default = "Stats::new")]
+    pub stats: Stats<PID>,
 }
 
 impl<PID: ID> State<PID> {
-    pub fn new(players: HashMap<PID, Role<PID>>) -> Self {
+    pub fn new(players: HashMap<PID, Role<PID>>, rules: &Rules) -> Self {
         let day_no = 0;
         let phase = Phase::Init;
-        let role_history = HashMap::new();
+        let charges = players
+            .iter()
+            .filter_map(|(&player, role)| Some((player, role.starting_charges(rules)?)))
+            .collect();
+        let modifiers = roledefs::modifiers(&rules.role_definitions, &players);
         State {
             day_no,
             players,
+            modifiers,
+            charges,
+            vote_weights: HashMap::new(),
+            designated: HashMap::new(),
             phase,
             timer: None,
-            role_history,
+            deadline: None,
+            stats: Stats::new(),
         }
     }
+
+    pub fn modifiers_of(&self, player: PID) -> Vec<Modifier> {
+        self.modifiers.get(&player).cloned().unwrap_or_default()
+    }
 }
 
+// A core with the ends of its channels the game talks to it through
+pub type CoreChannels<PID, GID> = (Core<PID, GID>, EventRx<PID>, CommandTx<PID>);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Core<PID: Debug + Eq + Hash, GID> {
     pub game_id: GID,
@@ -103,6 +147,14 @@ pub struct Core<PID: Debug + Eq + Hash, GID> {
     rules: Rules,
     #[serde(skip)]
     pub inter: Interface<PID>,
+    #[serde(skip)]
+    pub storage: Option<GameStore>,
+    log: ActionLog<PID>,
+    #[serde(skip, default = "clock::system")]
+    clock: ClockRef,
+    // Time the current action is being handled at. Timers are set relative to this
+    #[serde(skip)]
+    action_time: chrono::DateTime<chrono::Local>,
 }
 
 impl<PID: ID, GID: ID> Core<PID, GID> {
@@ -111,17 +163,94 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         players: HashMap<PID, Role<PID>>,
         rules: Rules,
     ) -> (Self, EventRx<PID>, CommandTx<PID>) {
-        let state = State::new(players);
+        let state = State::new(players, &rules);
+        let log = ActionLog::new(state.clone(), rules.clone());
         let (inter, event_rx, cmd_tx) = Interface::new_with_channels();
         let core = Core {
             game_id,
             state,
             rules,
             inter,
+            storage: None,
+            log,
+            clock: clock::system(),
+            action_time: chrono::Local::now(),
         };
         return (core, event_rx, cmd_tx);
     }
 
+    // Run timers off the given clock instead of wall time
+    pub fn with_clock(mut self, clock: ClockRef) -> Self {
+        self.action_time = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    // Give players modifiers on top of their roles, and any their custom role defines. Only
+    //   before the game starts.
+    pub fn with_modifiers(mut self, modifiers: HashMap<PID, Vec<Modifier>>) -> Self {
+        for (player, modifiers) in modifiers {
+            let current = self.state.modifiers.entry(player).or_default();
+            for modifier in modifiers {
+                if !current.contains(&modifier) {
+                    current.push(modifier);
+                }
+            }
+        }
+        self.log.initial_state.modifiers = self.state.modifiers.clone();
+        self
+    }
+
+    // Limit how many times players can use their night action, in place of any their role starts
+    //   with. Only before the game starts.
+    pub fn with_charges(mut self, charges: HashMap<PID, u8>) -> Self {
+        self.state.charges.extend(charges);
+        self.log.initial_state.charges = self.state.charges.clone();
+        self
+    }
+
+    // Snapshot the game to storage after every action
+    pub fn with_storage(mut self, storage: GameStore) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    // Rebuild a core from a snapshot with fresh channels. None if the game was already over.
+    // Timers are pushed back by the time since the snapshot, so they resume with the time
+    //   they had left when the game was saved.
+    pub fn restore(
+        saved: &SerializedGame,
+        clock: ClockRef,
+    ) -> Result<Option<CoreChannels<PID, GID>>, DeserializeGameError>
+    where
+        PID: DeserializeOwned,
+        GID: FromStr<Err = ParseIntError>,
+    {
+        let core: Core<PID, GID> = saved.try_into()?;
+        if let Phase::End { .. } = core.state.phase {
+            return Ok(None);
+        }
+        let (core, event_rx, cmd_tx) = core.take_channels();
+        let mut core = core.with_clock(clock);
+        let downtime = core.clock.now() - saved.saved_at;
+        for timer in [&mut core.state.timer, &mut core.state.deadline]
+            .into_iter()
+            .flatten()
+        {
+            timer.end_time += downtime;
+        }
+        Ok(Some((core, event_rx, cmd_tx)))
+    }
+
+    fn take_channels(self) -> CoreChannels<PID, GID> {
+        let (inter, event_rx, cmd_tx) = self
+            .inter
+            .take_channels()
+            .expect("Core channels already taken");
+        let core = Core { inter, ..self };
+        (core, event_rx, cmd_tx)
+    }
+
     pub async fn new_spawned(
         id: GID,
         players: HashMap<PID, Role<PID>>,
@@ -142,31 +271,48 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
 
     fn cancel_timers(&mut self) {
         self.state.timer = None;
+        self.state.deadline = None;
+    }
+
+    // Takes whichever of the timer and the phase deadline ends first
+    fn take_next_timer(&mut self) -> Option<Timer<PID>> {
+        let timer_end = self.state.timer.as_ref().map(|t| t.end_time);
+        let deadline_end = self.state.deadline.as_ref().map(|t| t.end_time);
+        match (timer_end, deadline_end) {
+            (Some(t), Some(d)) if d < t => self.state.deadline.take(),
+            (Some(_), _) => self.state.timer.take(),
+            (None, _) => self.state.deadline.take(),
+        }
     }
 
     pub async fn run(mut self) {
-        let mut quit = false;
-        while !quit {
-            // TODO: do we need this?
-            tokio::time::sleep(tokio::time::Duration::from_millis(1)).await;
-
-            // Check timer and perform action if required
-            let timer_action = match &self.state.timer {
-                Some(timer) => timer.check().await,
-                None => None,
-            };
-            if let Some(action) = timer_action {
-                let result = self.handle_action(action).await;
-                self.state.timer = None;
-                if let Err(e) = result {
-                    // TODO: How to handle this?
-                    println!("Error handling timer action!: {:?}", e);
+        loop {
+            // Recomputed every iteration, so a timer set, replaced or cancelled
+            //   by the last command is picked up before waiting again.
+            let timer_end = [&self.state.timer, &self.state.deadline]
+                .into_iter()
+                .flatten()
+                .map(|timer| timer.end_time)
+                .min();
+            let timer_wait = timer_end.map(|end_time| self.clock.sleep_until(end_time));
+
+            tokio::select! {
+                // Timers that are due fire before any queued command is handled
+                biased;
+                _ = Self::wait_for_timer(timer_wait) => {
+                    let Some(timer) = self.take_next_timer() else {
+                        continue;
+                    };
+                    if let Err(e) = self.accept_action(timer.data, true).await {
+                        // TODO: How to handle this?
+                        println!("Error handling timer action!: {:?}", e);
+                    }
+                }
+                cmd = self.inter.cmd_rx.recv() => {
+                    if self.handle_command(cmd).await {
+                        break;
+                    }
                 }
-                continue;
-            }
-
-            if self.try_handle_command().await {
-                quit = true;
             }
         }
 
@@ -177,56 +323,118 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         self.inter.send(Event::Close).await.unwrap();
     }
 
+    // Waits on the clock for the next timer, or forever if there is no timer running.
+    async fn wait_for_timer(sleep: Option<Pin<Box<dyn Future<Output = ()> + Send>>>) {
+        match sleep {
+            Some(sleep) => sleep.await,
+            None => std::future::pending().await,
+        }
+    }
+
     fn get_serialized_game(&self) -> Result<SerializedGame, SerializeGameError> {
         let state_json = serde_json::to_string_pretty(&self.state)?;
         let rules_toml = toml::to_string_pretty(&self.rules)?;
+        let log_json = serde_json::to_string(&self.log)?;
         Ok(SerializedGame {
             game_id: self.game_id.to_string(),
             state: state_json,
             rules: rules_toml,
+            log: log_json,
+            saved_at: self.clock.now(),
         })
     }
 
-    async fn try_handle_command(&mut self) -> bool {
-        match self.inter.cmd_rx.try_recv() {
-            Ok(Command::Action(action, response)) => {
-                let resp = self.handle_action(action).await;
+    async fn save_snapshot(&self) {
+        let Some(storage) = self.storage.clone() else {
+            return;
+        };
+        let game_id = self.game_id.to_string();
+        let snapshot = match self.state.phase {
+            Phase::End { .. } => None,
+            _ => Some(self.get_serialized_game()),
+        };
+        // File writes block, so they run off the async runtime. Waiting on them keeps snapshots in order
+        let result = tokio::task::spawn_blocking(move || match snapshot {
+            None => storage.remove(&game_id),
+            Some(saved_game) => storage.save(&saved_game?),
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            // TODO: How to handle this?
+            Ok(Err(e)) => println!("Error saving game {:?}: {:?}", self.game_id, e),
+            Err(e) => println!("Error saving game {:?}: {:?}", self.game_id, e),
+        }
+    }
+
+    // Returns true if the core should quit.
+    async fn handle_command(&mut self, cmd: Option<Command<PID>>) -> bool {
+        match cmd {
+            Some(Command::Action(action, response)) => {
+                let resp = self.accept_action(action, false).await;
                 response.send(resp).expect("Response channel error: {:?}");
             }
-            Ok(Command::State(response)) => {
+            Some(Command::State(response)) => {
                 response
                     .send(Ok(self.state.clone()))
                     .expect("Response channel error: {:?}");
             }
-            Ok(Command::Rules(response)) => {
+            Some(Command::Rules(response)) => {
                 response
                     .send(Ok(self.rules.clone()))
                     .expect("Response channel error: {:?}");
             }
-            Ok(Command::Serialize(response)) => {
-                let state_json = serde_json::to_string_pretty(&self.state);
-                let rules_toml = toml::to_string_pretty(&self.rules);
-                let saved_game = SerializedGame {
-                    game_id: self.game_id.to_string(),
-                    state: state_json.unwrap(),
-                    rules: rules_toml.unwrap(),
-                };
+            Some(Command::Stats(response)) => {
+                response
+                    .send(Ok(self.state.stats.clone()))
+                    .expect("Response channel error: {:?}");
+            }
+            Some(Command::View(player, response)) => {
                 response
-                    .send(Ok(saved_game))
+                    .send(self.player_view(player))
+                    .expect("Response channel error: {:?}");
+            }
+            Some(Command::Log(response)) => {
+                response
+                    .send(Ok(self.log.clone()))
+                    .expect("Response channel error: {:?}");
+            }
+            Some(Command::Serialize(response)) => {
+                response
+                    .send(self.get_serialized_game())
                     .expect("Response channel error: {:?}");
             }
 
-            Ok(Command::Close) => {
+            Some(Command::Close) => {
                 return true;
             }
-            Err(mpsc::error::TryRecvError::Empty) => {}
-            Err(mpsc::error::TryRecvError::Disconnected) => {
+            None => {
                 return true;
             }
         }
         return false;
     }
 
+    // Handle an action from a command or timer. Accepted actions are logged and the
+    //   game is snapshot to storage.
+    async fn accept_action(
+        &mut self,
+        action: Action<PID>,
+        from_timer: bool,
+    ) -> Result<(), CoreError<PID>> {
+        self.action_time = self.clock.now();
+        let result = self.handle_action(action.clone()).await;
+        if result.is_ok() {
+            self.log.entries.push(LoggedAction {
+                time: self.action_time,
+                action,
+                from_timer,
+            });
+        }
+        self.save_snapshot().await;
+        result
+    }
+
     async fn handle_action(&mut self, action: Action<PID>) -> Result<(), CoreError<PID>> {
         let result = match action {
             Action::Start => self.start().await,
@@ -235,40 +443,84 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
             Action::Reveal { player } => self.reveal(player).await,
             Action::Target { actor, target } => self.target(actor, target).await,
             Action::Scheme { actor, mark } => self.scheme(actor, mark).await,
+            Action::Designate { actor, killer } => self.designate(actor, killer).await,
             Action::Avenge { avenger, victim } => self.avenge(avenger, victim).await,
             Action::Elect { candidate, hammer } => self.elect(candidate, hammer).await,
             Action::Dawn => self.dawn().await,
+            Action::Deadline => self.deadline().await,
         };
         result
     }
 
     async fn start(&mut self) -> Result<(), CoreError<PID>> {
-        self.inter
-            .send(Event::Start {
-                players: self.state.players.clone(),
-            })
-            .await?;
-
         let Phase::Init = self.state.phase else {
             let actual = self.state.phase.kind();
             let expected = PhaseKind::Init;
             return Err(CoreError::InvalidPhase { actual, expected });
         };
 
+        let players = self.sorted_players();
+
+        self.inter
+            .send(Event::Start {
+                players: players.iter().map(|&(player, _)| player).collect(),
+                disclosure: self.start_disclosure(),
+            })
+            .await?;
+
+        for &(player, role) in &players {
+            let teammates = self.teammates(player, role);
+            self.inter
+                .send(Event::StartRole {
+                    player,
+                    role: role.as_known(),
+                    modifiers: self.state.modifiers_of(player),
+                    teammates,
+                })
+                .await?;
+        }
+
         // Add initial roles to rolehist
-        for (player, role) in &self.state.players {
-            self.state.role_history.insert(*player, vec![*role]);
+        for (&player, &role) in &self.state.players {
+            let modifiers = self.state.modifiers_of(player);
+            let record = RoleRecord { role, modifiers };
+            self.state.stats.role_history.insert(player, vec![record]);
         }
         // For now assume start event
         let n = self.state.players.len();
         if n % 2 == 0 {
             self.to_night().await?;
         } else {
-            self.to_day(None).await?;
+            self.to_day(None, HashMap::new()).await?;
         }
         Ok(())
     }
 
+    fn start_disclosure(&self) -> StartDisclosure {
+        let roles = self.state.players.values();
+        match self.rules.info_rules.start_roles {
+            StartRoles::Roles => {
+                let mut counts = BTreeMap::new();
+                for role in roles {
+                    *counts.entry(role.kind()).or_default() += 1;
+                }
+                StartDisclosure::Roles(counts)
+            }
+            StartRoles::Teams => {
+                let mut counts = BTreeMap::new();
+                for role in roles {
+                    *counts.entry(role.team()).or_default() += 1;
+                }
+                StartDisclosure::Teams(counts)
+            }
+            StartRoles::Mafia => {
+                let mafia = roles.filter(|role| role.team().is_faction()).count();
+                let non_mafia = self.state.players.len() - mafia;
+                StartDisclosure::Mafia { mafia, non_mafia }
+            }
+        }
+    }
+
     async fn vote(
         &mut self,
         voter: PID,
@@ -294,6 +546,11 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
             return Ok(());
         }
 
+        self.state.stats.votes.push(VoteRecord {
+            day: self.state.day_no,
+            voter,
+            ballot,
+        });
         self.inter
             .send(Event::Vote {
                 voter,
@@ -312,7 +569,7 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         ballot: Option<Choice<PID>>,
         former_ballot: Option<Choice<PID>>,
     ) -> Result<Option<Vec<PID>>, CoreError<PID>> {
-        let n = self.state.players.len();
+        let weights = self.vote_weights();
         let Phase::Day { votes, .. } = &self.state.phase else {
             return Err(CoreError::InvalidPhase {
                 actual: self.state.phase.kind(),
@@ -326,7 +583,7 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
                     data: Action::Elect { candidate, .. },
                     ..
                 }) if candidate == former_candidate => {
-                    if let None = Self::check_quorum(votes, n, former_candidate) {
+                    if let None = Self::check_quorum(votes, &weights, former_candidate) {
                         self.state.timer = None;
                     }
                 }
@@ -336,11 +593,11 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
 
         if let Some(candidate) = ballot {
             // Check if new election is imminent
-            if let Some(voters) = Self::check_quorum(votes, n, candidate) {
+            if let Some(voters) = Self::check_quorum(votes, &weights, candidate) {
                 // Set election timer (if not already set)
                 if let None = self.state.timer {
                     let duration = self.rules.timer_rules.election_imminent_time;
-                    let end_time = chrono::offset::Local::now() + duration;
+                    let end_time = self.action_time + duration;
                     self.state.timer = Some(Timer {
                         end_time,
                         data: Action::Elect { candidate, hammer },
@@ -355,11 +612,14 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         Ok(None)
     }
 
+    // Weighted votes for the candidate against a threshold of the living players' votes.
+    // Negative votes count against the candidate, but not towards the threshold.
     fn check_quorum(
         votes: &HashMap<PID, Choice<PID>>,
-        n: usize,
+        weights: &HashMap<PID, i32>,
         candidate: Choice<PID>,
     ) -> Option<Vec<PID>> {
+        let n: i32 = weights.values().map(|&weight| weight.max(0)).sum();
         let threshold = match candidate {
             Choice::Player(_) => n / 2 + 1,
             Choice::Abstain => (n + 1) / 2,
@@ -371,20 +631,25 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
                 voters.push(*voter);
             }
         }
+        voters.sort();
 
-        if voters.len() >= threshold {
+        let tally: i32 = voters
+            .iter()
+            .map(|voter| weights.get(voter).copied().unwrap_or(0))
+            .sum();
+        if tally >= threshold {
             return Some(voters);
         } else {
             return None;
         }
     }
 
-    async fn reveal(&self, player: PID) -> Result<(), CoreError<PID>> {
+    async fn reveal(&mut self, player: PID) -> Result<(), CoreError<PID>> {
         let role = Self::validate_player(&self.state.players, player)?;
-        // Check if the role is a celeb
-        if role != Role::CELEB {
-            let actual = role.kind();
-            return Err(CoreError::ExpectedCeleb { actual });
+        // Check if the role can reveal
+        if role != Role::CELEB && role != Role::MAYOR {
+            let role = role.kind();
+            return Err(CoreError::ExpectedRevealingRole { role });
         }
         // Check that Phase is Day
         let Phase::Day { blocks, .. } = &self.state.phase else {
@@ -394,22 +659,40 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         };
 
         // Check for a reveal block
-        if blocks.contains_key(&player) {
-            let blocked = player;
-            let blockers = blocks[&player].clone();
+        if let Some(blockers) = blocks.get(&player) {
+            let level = self.rules.info_rules.block;
+            let blockers = blockers
+                .iter()
+                .filter_map(|&blocker| self.known_role(blocker))
+                .map(|role| RoleInfo::new(role, level))
+                .collect();
             self.inter
-                .send(Event::EvidentBlock { blocked, blockers })
+                .send(Event::EvidentBlock {
+                    blocked: player,
+                    blockers,
+                })
                 .await?;
             return Ok(());
         }
 
         self.inter.send(Event::Reveal { player, role }).await?;
+
+        if role == Role::MAYOR {
+            self.state.vote_weights.insert(player, MAYOR_VOTES);
+            // A vote already cast may now be enough for an election
+            let Phase::Day { votes, .. } = &self.state.phase else {
+                return Ok(());
+            };
+            if let Some(&ballot) = votes.get(&player) {
+                self.check_election(player, Some(ballot), None).await?;
+            }
+        }
         Ok(())
     }
 
     async fn target(&mut self, actor: PID, target: Choice<PID>) -> Result<(), CoreError<PID>> {
         let role = Self::validate_player(&self.state.players, actor)?;
-        if !role.is_targeting() {
+        if !Self::can_target(&self.state.modifiers, actor, &role) {
             let role = role.kind();
             return Err(CoreError::ExpectedTargetingRole { role });
         }
@@ -419,7 +702,7 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
 
         // Check if the phase is night
         let Phase::Night {
-            targets, scheme, ..
+            targets, schemes, ..
         } = &mut self.state.phase
         else {
             return Err(CoreError::InvalidPhase {
@@ -428,15 +711,43 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
             });
         };
 
+        if let (ActionType::Save, Choice::Player(player)) = (role.action_type(), target) {
+            let rules = &self.rules.doctor_rules;
+            let self_save = player == actor;
+            let consecutive =
+                self.state.stats.previous_target(actor, self.state.day_no) == Some(target);
+            if (self_save && !rules.allow_self_save)
+                || (consecutive && !rules.allow_consecutive_saves)
+            {
+                return Err(CoreError::IllegalSaveTarget {
+                    actor,
+                    target: player,
+                });
+            }
+        }
+
         // Check for Stripper Overload
         if role.kind() == RoleKind::STRIPPER && target != Choice::Abstain {
-            if let Some((killer, mark)) = scheme {
+            if let Some((killer, mark)) = schemes.get(&role.team()) {
                 if *killer == actor && *mark != Choice::Abstain {
                     return Err(CoreError::StripperOverload { actor });
                 }
             }
         }
 
+        // Targeting a player spends a charge. Changing the target tonight doesn't spend another
+        let had_target = matches!(targets.get(&actor), Some(Choice::Player(_)));
+        if let Some(charges) = self.state.charges.get_mut(&actor) {
+            match (had_target, target) {
+                (false, Choice::Player(_)) if *charges == 0 => {
+                    return Err(CoreError::NoChargesLeft { actor });
+                }
+                (false, Choice::Player(_)) => *charges -= 1,
+                (true, Choice::Abstain) => *charges += 1,
+                _ => {}
+            }
+        }
+
         targets.insert(actor, target);
         self.inter.send(Event::Target { actor, target }).await?;
 
@@ -455,10 +766,7 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         }
 
         // Check if the phase is night
-        let Phase::Night {
-            targets, scheme, ..
-        } = &mut self.state.phase
-        else {
+        let Phase::Night { targets, picks, .. } = &mut self.state.phase else {
             return Err(CoreError::InvalidPhase {
                 actual: self.state.phase.kind(),
                 expected: PhaseKind::Night,
@@ -474,26 +782,165 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
             }
         }
 
-        scheme.replace((actor, mark));
-        self.inter.send(Event::Scheme { actor, mark }).await?;
+        // A new pick replaces the member's last one
+        let team = role.team();
+        let faction_picks = picks.entry(team).or_default();
+        faction_picks.retain(|&(member, _)| member != actor);
+        faction_picks.push((actor, mark));
+        self.inter
+            .send(Event::SchemePicks {
+                team,
+                picks: faction_picks.clone(),
+            })
+            .await?;
+
+        self.settle_scheme(team).await?;
+        self.check_dawn()?;
+        Ok(())
+    }
+
+    // Name the member who decides the faction's kill, for designated scheme decisions
+    async fn designate(&mut self, actor: PID, killer: PID) -> Result<(), CoreError<PID>> {
+        let role = Self::validate_player(&self.state.players, actor)?;
+        if !role.is_scheming() {
+            let role = role.kind();
+            return Err(CoreError::ExpectedSchemingRole { role });
+        }
+        let _ = Self::validate_player(&self.state.players, killer)?;
+        let Phase::Night { .. } = self.state.phase else {
+            return Err(CoreError::InvalidPhase {
+                actual: self.state.phase.kind(),
+                expected: PhaseKind::Night,
+            });
+        };
+
+        let team = role.team();
+        let members = Self::faction_members(&self.state.players, team);
+        if !members.contains(&killer) {
+            return Err(CoreError::InvalidOption {
+                actual: killer,
+                options: members,
+            });
+        }
+        self.state.designated.insert(team, killer);
+        self.inter.send(Event::Designate { team, killer }).await?;
 
+        self.settle_scheme(team).await?;
         self.check_dawn()?;
         Ok(())
     }
 
+    // Decide the faction's kill from its picks so far, and announce it if it changed
+    async fn settle_scheme(&mut self, team: Team) -> Result<(), CoreError<PID>> {
+        let Phase::Night { schemes, picks, .. } = &mut self.state.phase else {
+            return Err(CoreError::InvalidPhase {
+                actual: self.state.phase.kind(),
+                expected: PhaseKind::Night,
+            });
+        };
+        let members = Self::faction_members(&self.state.players, team);
+        let designated = self.state.designated.get(&team).copied();
+        let faction_picks = picks.get(&team).map(Vec::as_slice).unwrap_or_default();
+        let rules = &self.rules.night_rules;
+        let decision = Self::decide_scheme(rules, &members, designated, faction_picks, false);
+        match decision {
+            Some(scheme) if schemes.get(&team) != Some(&scheme) => {
+                schemes.insert(team, scheme);
+                let (actor, mark) = scheme;
+                self.inter.send(Event::Scheme { team, actor, mark }).await?;
+            }
+            Some(_) => {}
+            None => {
+                // The faction came unsettled, so dawn has to wait for it again
+                schemes.remove(&team);
+                if matches!(
+                    self.state.timer,
+                    Some(Timer {
+                        data: Action::Dawn,
+                        ..
+                    })
+                ) {
+                    self.state.timer = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The faction's kill, as (killer, mark), if its picks settle on one.
+    // Forced settles a majority decision even if not every member has picked.
+    fn decide_scheme(
+        rules: &NightRules,
+        members: &[PID],
+        designated: Option<PID>,
+        picks: &[(PID, Choice<PID>)],
+        forced: bool,
+    ) -> Option<(PID, Choice<PID>)> {
+        match rules.scheme_decision {
+            SchemeDecision::Last => picks.last().copied(),
+            SchemeDecision::Designated => {
+                let killer = designated
+                    .filter(|killer| members.contains(killer))
+                    .or(members.first().copied())?;
+                picks.iter().find(|&&(member, _)| member == killer).copied()
+            }
+            SchemeDecision::Majority => {
+                let count = |mark: Choice<PID>| picks.iter().filter(|&&(_, m)| m == mark).count();
+                // The killer is the first member to pick the mark
+                if let Some(&scheme) = picks
+                    .iter()
+                    .find(|&&(_, mark)| count(mark) * 2 > members.len())
+                {
+                    return Some(scheme);
+                }
+                if !forced && picks.len() < members.len() {
+                    return None;
+                }
+                // Without a majority the mark with the most picks is the kill, unless there's a tie
+                let most = picks.iter().map(|&(_, mark)| count(mark)).max()?;
+                let first = *picks.iter().find(|&&(_, mark)| count(mark) == most)?;
+                let tied = picks
+                    .iter()
+                    .any(|&(_, mark)| mark != first.1 && count(mark) == most);
+                match rules.scheme_tie_break {
+                    _ if !tied => Some(first),
+                    SchemeTieBreak::NoKill => Some((first.0, Choice::Abstain)),
+                    SchemeTieBreak::FirstPick => Some(first),
+                }
+            }
+        }
+    }
+
+    // Living members of a faction in player order
+    fn faction_members(players: &HashMap<PID, Role<PID>>, team: Team) -> Vec<PID> {
+        let mut members: Vec<PID> = players
+            .iter()
+            .filter(|(_, role)| role.team() == team)
+            .map(|(&player, _)| player)
+            .collect();
+        members.sort();
+        members
+    }
+
     fn check_dawn(&mut self) -> Result<bool, CoreError<PID>> {
-        let Phase::Night { targets, scheme } = &mut self.state.phase else {
+        let Phase::Night {
+            targets, schemes, ..
+        } = &mut self.state.phase
+        else {
             let actual = self.state.phase.kind();
             let expected = PhaseKind::Night;
             return Err(CoreError::InvalidPhase { actual, expected });
         };
-        if let None = scheme {
-            return Ok(false);
+        // Check that every faction with a living member has schemed
+        for role in self.state.players.values() {
+            if role.is_scheming() && !schemes.contains_key(&role.team()) {
+                return Ok(false);
+            }
         }
         // Check that every targeting role has a target
-        for (player, role) in &self.state.players {
-            if role.is_targeting() {
-                if !targets.contains_key(player) {
+        for (&player, role) in &self.state.players {
+            if Self::awaits_target(&self.state.modifiers, &self.state.charges, player, role) {
+                if !targets.contains_key(&player) {
                     return Ok(false);
                 }
             }
@@ -501,7 +948,7 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         // Schedule dawn!
         if let None = self.state.timer {
             let duration = self.rules.timer_rules.dawn_imminent_time;
-            let end_time = chrono::offset::Local::now() + duration;
+            let end_time = self.action_time + duration;
             self.state.timer = Some(Timer {
                 end_time,
                 data: Action::Dawn,
@@ -512,9 +959,9 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
     }
 
     async fn elect(&mut self, candidate: Choice<PID>, hammer: PID) -> Result<(), CoreError<PID>> {
-        let n = self.state.players.len();
+        let weights = self.vote_weights();
         // Ensure the phase is Day
-        let Phase::Day { votes, .. } = &mut self.state.phase else {
+        let Phase::Day { votes, .. } = &self.state.phase else {
             let actual = self.state.phase.kind();
             let expected = PhaseKind::Day;
             return Err(CoreError::InvalidPhase { actual, expected });
@@ -524,15 +971,40 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
             let _ = Self::validate_player(&self.state.players, player)?;
         }
 
-        let Some(voters) = Self::check_quorum(votes, n, candidate) else {
+        let Some(voters) = Self::check_quorum(votes, &weights, candidate) else {
             return Err(CoreError::ExpectedElection { candidate });
         };
 
+        self.election(candidate, hammer, voters).await
+    }
+
+    async fn election(
+        &mut self,
+        candidate: Choice<PID>,
+        hammer: PID,
+        voters: Vec<PID>,
+    ) -> Result<(), CoreError<PID>> {
+        let weights = self.vote_weights();
+        let weighted: Vec<(PID, i32)> = voters
+            .iter()
+            .map(|voter| (*voter, weights.get(voter).copied().unwrap_or(1)))
+            .collect();
+        self.state.stats.elections.push(ElectionRecord {
+            day: self.state.day_no,
+            candidate,
+            hammer,
+            voters: voters.clone(),
+            weights: weighted
+                .iter()
+                .copied()
+                .filter(|&(_, weight)| weight != 1)
+                .collect(),
+        });
         self.inter
             .send(Event::Election {
                 candidate,
                 hammer,
-                voters: voters.clone(),
+                voters: weighted,
             })
             .await?;
 
@@ -554,19 +1026,114 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         Ok(())
     }
 
+    async fn deadline(&mut self) -> Result<(), CoreError<PID>> {
+        let phase = self.state.phase.kind();
+        let weights = self.vote_weights();
+        match &mut self.state.phase {
+            Phase::Day { votes, .. } => {
+                // An imminent election still goes ahead, whatever the deadline rule
+                let pending = match self.state.timer {
+                    Some(Timer {
+                        data: Action::Elect { candidate, hammer },
+                        ..
+                    }) => Self::check_quorum(votes, &weights, candidate)
+                        .map(|voters| (candidate, hammer, voters)),
+                    _ => None,
+                };
+                let outcome = pending.or_else(|| match self.rules.timer_rules.day_deadline {
+                    DayDeadline::NoElimination => None,
+                    DayDeadline::Plurality => Self::check_plurality(votes, &weights),
+                });
+                self.state.timer = None;
+                self.inter.send(Event::DeadlinePassed { phase }).await?;
+                match outcome {
+                    Some((candidate, hammer, voters)) => {
+                        self.election(candidate, hammer, voters).await
+                    }
+                    None => self.to_night().await,
+                }
+            }
+            Phase::Night {
+                targets,
+                schemes,
+                picks,
+            } => {
+                // Targeting roles that haven't acted abstain
+                let (modifiers, charges) = (&self.state.modifiers, &self.state.charges);
+                for (&player, role) in &self.state.players {
+                    if Self::awaits_target(modifiers, charges, player, role) {
+                        targets.entry(player).or_insert(Choice::Abstain);
+                    }
+                }
+                // Factions that haven't settled go with what they have
+                for (&team, faction_picks) in picks.iter() {
+                    if schemes.contains_key(&team) {
+                        continue;
+                    }
+                    let members = Self::faction_members(&self.state.players, team);
+                    let designated = self.state.designated.get(&team).copied();
+                    let rules = &self.rules.night_rules;
+                    let decision =
+                        Self::decide_scheme(rules, &members, designated, faction_picks, true);
+                    if let Some(scheme) = decision {
+                        schemes.insert(team, scheme);
+                    }
+                }
+                self.state.timer = None;
+                self.inter.send(Event::DeadlinePassed { phase }).await?;
+                self.dawn().await
+            }
+            _ => Err(CoreError::InvalidPhase {
+                actual: phase,
+                expected: PhaseKind::Day,
+            }),
+        }
+    }
+
+    // The choice with the most weighted votes, a voter for it and all its voters. None on a tie
+    fn check_plurality(
+        votes: &HashMap<PID, Choice<PID>>,
+        weights: &HashMap<PID, i32>,
+    ) -> Option<(Choice<PID>, PID, Vec<PID>)> {
+        let mut tally: HashMap<Choice<PID>, Vec<PID>> = HashMap::new();
+        for (&voter, &choice) in votes {
+            tally.entry(choice).or_default().push(voter);
+        }
+        let total = |voters: &Vec<PID>| -> i32 {
+            voters
+                .iter()
+                .map(|voter| weights.get(voter).copied().unwrap_or(0))
+                .sum()
+        };
+        let most = tally.values().map(total).max().filter(|&most| most > 0)?;
+        let mut leaders = tally
+            .into_iter()
+            .filter(|(_, voters)| total(voters) == most);
+        let (candidate, mut voters) = leaders.next()?;
+        if leaders.next().is_some() {
+            return None;
+        }
+        voters.sort();
+        let hammer = *voters.first()?;
+        Some((candidate, hammer, voters))
+    }
+
     fn collect_night_actions(
         players: &HashMap<PID, Role<PID>>,
         targets: &HashMap<PID, Choice<PID>>,
+        rules: &Rules,
     ) -> Result<(BinaryHeap<NightAction<PID>>, BinaryHeap<NightAction<PID>>), CoreError<PID>> {
         let mut early_actions = BinaryHeap::new();
         let mut late_actions = BinaryHeap::new();
-        for (&actor, &target) in targets.iter() {
+        let mut targets: Vec<_> = targets.iter().collect();
+        targets.sort_by_key(|&(&actor, _)| actor);
+        for (&actor, &target) in targets {
             let Choice::Player(target) = target else {
                 continue;
             };
             let role = Core::<PID, GID>::validate_player(players, actor)?;
             let _ = Core::<PID, GID>::validate_player(players, target)?;
-            let Some(priority) = role.night_action_priority() else {
+            let Some(priority) = role.night_action_priority(rules) else {
                 return Err(CoreError::ExpectedTargetingRole { role: role.kind() });
             };
             let action = NightAction {
@@ -575,7 +1142,7 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
                 target,
                 priority,
             };
-            if priority < 0 {
+            if priority > 0 {
                 early_actions.push(action);
             } else {
                 late_actions.push(action);
@@ -586,7 +1153,7 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
 
     async fn dawn(&mut self) -> Result<(), CoreError<PID>> {
         let Phase::Night {
-            targets, scheme, ..
+            targets, schemes, ..
         } = &self.state.phase
         else {
             let actual = self.state.phase.kind();
@@ -597,39 +1164,176 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         self.inter.send(Event::Dawn).await?;
 
         let (early_night_actions, late_night_actions) =
-            Self::collect_night_actions(&self.state.players, targets)?;
-
-        let mut dawn_state: DawnState<PID> = DawnState {
-            blocks: HashMap::new(),
-            saves: HashMap::new(),
-            killed: HashMap::new(),
-        };
+            Self::collect_night_actions(&self.state.players, targets, &self.rules)?;
+
+        let mut dawn_state = DawnState::new(
+            self.state.day_no,
+            self.state.players.clone(),
+            self.state.modifiers.clone(),
+        );
+        dawn_state.acting = targets
+            .iter()
+            .filter(|(_, target)| matches!(target, Choice::Player(_)))
+            .map(|(&actor, _)| actor)
+            .collect();
 
         self.perform_night_actions(early_night_actions, &mut dawn_state)
             .await?;
 
-        self.perform_scheme(scheme, &mut dawn_state).await?;
+        self.perform_schemes(schemes, &mut dawn_state).await?;
+
+        let (targets, schemes) = (targets.clone(), schemes.clone());
+
+        self.perform_night_actions(late_night_actions, &mut dawn_state)
+            .await?;
+
+        self.spend_modifiers(&dawn_state);
+        // Record the night before anyone is eliminated
+        self.record_night(targets, schemes, &mut dawn_state);
 
-        // Perform Kills (first killer does the kill, but end of game isn't checked until all kills are performed)
+        // Perform Kills from every killer (first killer does the kill, but end of game isn't
+        //   checked until all kills are performed)
         if dawn_state.killed.len() > 0 {
             let mut eliminations = Vec::new();
             for (&mark, killers) in &dawn_state.killed {
                 eliminations.push((mark, killers.first().unwrap().clone()));
             }
-            if self.eliminate_many(eliminations).await? {
+            eliminations.sort();
+            let level = self.rules.info_rules.kill;
+            if self.eliminate_many(eliminations, level).await? {
                 return Ok(());
             }
         } else {
             self.inter.send(Event::NoNightKill).await?;
         }
 
-        self.perform_night_actions(late_night_actions, &mut dawn_state)
+        self.to_day(Some(dawn_state.blocks), dawn_state.stolen)
             .await?;
-
-        self.to_day(Some(dawn_state.blocks)).await?;
         Ok(())
     }
 
+    // Adds the night's targets, kills, saves, blocks and investigations to the stats
+    fn record_night(
+        &mut self,
+        targets: HashMap<PID, Choice<PID>>,
+        schemes: HashMap<Team, (PID, Choice<PID>)>,
+        dawn_state: &mut DawnState<PID>,
+    ) {
+        let night = dawn_state.night;
+        let stats = &mut self.state.stats;
+
+        let mut targets: Vec<_> = targets.into_iter().collect();
+        targets.sort_by_key(|&(actor, _)| actor);
+        for (actor, target) in targets {
+            let outcome = match target {
+                Choice::Abstain => NightOutcome::Abstained,
+                Choice::Player(_) => *dawn_state
+                    .outcomes
+                    .get(&actor)
+                    .unwrap_or(&NightOutcome::Performed),
+            };
+            stats.night_actions.push(NightRecord {
+                night,
+                actor,
+                role: dawn_state.players[&actor].kind(),
+                target,
+                scheme: false,
+                outcome,
+            });
+        }
+        let mut schemes: Vec<_> = schemes.into_iter().collect();
+        schemes.sort_by_key(|&(team, _)| team);
+        for (team, (killer, mark)) in schemes {
+            // A passed kill is recorded against the teammate who made it
+            let killer = dawn_state.passed.get(&killer).copied().unwrap_or(killer);
+            let outcome = match mark {
+                Choice::Abstain => NightOutcome::Abstained,
+                Choice::Player(_) if dawn_state.outcomes.contains_key(&killer) => {
+                    dawn_state.outcomes[&killer]
+                }
+                Choice::Player(mark) if Self::faction_killed(dawn_state, mark, team) => {
+                    NightOutcome::Performed
+                }
+                Choice::Player(_) => NightOutcome::Saved,
+            };
+            stats.night_actions.push(NightRecord {
+                night,
+                actor: killer,
+                role: dawn_state.players[&killer].kind(),
+                target: mark,
+                scheme: true,
+                outcome,
+            });
+        }
+
+        let mut kills: Vec<_> = dawn_state
+            .killed
+            .iter()
+            .flat_map(|(&mark, killers)| killers.iter().map(move |&killer| (mark, killer)))
+            .collect();
+        kills.sort();
+        for (mark, killer) in kills {
+            stats.kills.push(KillRecord {
+                night,
+                killer,
+                mark,
+            });
+        }
+        for &(savior, mark) in &dawn_state.rescued {
+            stats.saves.push(SaveRecord {
+                night,
+                savior,
+                mark,
+            });
+        }
+        let mut blocks: Vec<_> = dawn_state
+            .blocks
+            .iter()
+            .flat_map(|(&blocked, blockers)| {
+                blockers.iter().map(move |&blocker| (blocked, blocker))
+            })
+            .collect();
+        blocks.sort();
+        for (blocked, blocker) in blocks {
+            stats.blocks.push(BlockRecord {
+                night,
+                blocker,
+                blocked,
+            });
+        }
+        stats.investigations.append(&mut dawn_state.investigations);
+    }
+
+    fn faction_killed(dawn_state: &DawnState<PID>, mark: PID, team: Team) -> bool {
+        dawn_state.killed.get(&mark).is_some_and(|killers| {
+            killers
+                .iter()
+                .any(|killer| dawn_state.players[killer].team() == team)
+        })
+    }
+
+    // Bulletproof is lost once it stops a kill, and a one-shot is used once its action goes through
+    fn spend_modifiers(&mut self, dawn_state: &DawnState<PID>) {
+        for mark in &dawn_state.absorbed {
+            if let Some(modifiers) = self.state.modifiers.get_mut(mark) {
+                modifiers.retain(|&modifier| modifier != Modifier::Bulletproof);
+            }
+        }
+        for (actor, _) in &dawn_state.visits {
+            if !dawn_state.acting.contains(actor) {
+                // A faction's kill or an abstain doesn't use up a night action
+                continue;
+            }
+            if let Some(modifiers) = self.state.modifiers.get_mut(actor) {
+                for modifier in modifiers.iter_mut() {
+                    if *modifier == Modifier::OneShot(false) {
+                        *modifier = Modifier::OneShot(true);
+                    }
+                }
+            }
+        }
+    }
+
     // Note: Night actions are performed in batches. All actions of a given
     //   priority create their changes at once, then all changes are applied at once.
     async fn perform_night_actions(
@@ -644,7 +1348,10 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
 
             while next.is_some_and(|f| f.priority == current_priority) {
                 let action = actions.pop().expect("Checked for some above!");
-                let new_changes = action.perform(dawn_state, &self.inter.event_tx).await?;
+                let mut new_changes = action
+                    .perform(dawn_state, &self.rules, &self.inter.event_tx)
+                    .await?;
+                dawn_state.apply_absorbs(&mut new_changes);
                 changes.extend(new_changes);
                 next = actions.peek();
             }
@@ -653,12 +1360,22 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         Ok(())
     }
 
-    async fn perform_scheme(
+    // Every faction's kill is resolved as one batch, so factions can kill each other's killers
+    async fn perform_schemes(
         &self,
-        scheme: &Option<(PID, Choice<PID>)>,
+        schemes: &HashMap<Team, (PID, Choice<PID>)>,
         dawn_state: &mut DawnState<PID>,
     ) -> Result<(), CoreError<PID>> {
-        let changes = NightAction::perform_scheme(scheme, dawn_state, &self.inter.event_tx).await?;
+        let mut schemes: Vec<_> = schemes.iter().collect();
+        schemes.sort_by_key(|&(&team, _)| team);
+        let mut changes = Vec::new();
+        for (_, scheme) in schemes {
+            let event_tx = &self.inter.event_tx;
+            let mut new_changes =
+                NightAction::perform_scheme(scheme, dawn_state, &self.rules, event_tx).await?;
+            dawn_state.apply_absorbs(&mut new_changes);
+            changes.extend(new_changes);
+        }
         dawn_state.apply_changes(changes);
         Ok(())
     }
@@ -701,7 +1418,8 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         // change IDIOT's role to win state
         self.refocus(avenger, Role::IDIOT(true)).await?;
 
-        self.eliminate_many(vec![(target, avenger), (avenger, hammer)])
+        let level = self.rules.info_rules.election;
+        self.eliminate_many(vec![(target, avenger), (avenger, hammer)], level)
             .await?;
 
         self.to_night().await?;
@@ -709,20 +1427,28 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
     }
 
     async fn eliminate(&mut self, player: PID, proxy: PID) -> Result<bool, CoreError<PID>> {
-        self.eliminate_many(vec![(player, proxy)]).await
+        let level = self.rules.info_rules.election;
+        self.eliminate_many(vec![(player, proxy)], level).await
     }
 
+    // level is how much of the eliminated roles is revealed
     async fn eliminate_many(
         &mut self,
         eliminations: Vec<(PID, PID)>,
+        level: InfoLevel,
     ) -> Result<bool, CoreError<PID>> {
         for (player, proxy) in eliminations {
             let role = Self::validate_player(&self.state.players, player)?;
 
             self.check_refocus(player, proxy).await?;
 
+            let role = RoleInfo::new(role, level);
             self.inter.send(Event::Eliminate { player, role }).await?;
             self.state.players.remove(&player);
+            self.state
+                .stats
+                .days_survived
+                .insert(player, self.state.day_no);
         }
         // Check for end of game
         if let Some(winner) = self.check_end() {
@@ -732,42 +1458,84 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         Ok(false)
     }
 
-    fn check_end(&self) -> Option<Team> {
+    // The winning team once the game is over, or None inside for a draw
+    fn check_end(&self) -> Option<Option<Team>> {
         let n = self.state.players.len();
-        let n_mafia = self
-            .state
-            .players
-            .values()
-            .filter(|&&role| role.team() == Team::Mafia)
-            .count();
+        let mut factions: BTreeMap<Team, usize> = BTreeMap::new();
+        for role in self.state.players.values() {
+            if role.team().is_faction() {
+                *factions.entry(role.team()).or_default() += 1;
+            }
+        }
 
-        if n_mafia == 0 {
+        if n == 0 {
+            // Everyone killed each other off
+            return Some(None);
+        }
+        if factions.is_empty() {
             // Town wins!
-            return Some(Team::Town);
-        } else if n - n_mafia <= n_mafia {
-            // Mafia wins!
-            return Some(Team::Mafia);
+            return Some(Some(Team::Town));
+        }
+        // A faction wins once it's the last one left and has parity with everyone else
+        if let [(&team, &n_faction)] = factions.iter().collect::<Vec<_>>()[..] {
+            if n - n_faction <= n_faction {
+                return Some(Some(team));
+            }
+            return None;
+        }
+        // Only rival factions are left and none has a majority, so none can win
+        if factions.values().sum::<usize>() == n && factions.values().all(|&f| 2 * f <= n) {
+            return Some(None);
         }
         return None;
     }
 
-    async fn end(&mut self, winner: Team) -> Result<(), CoreError<PID>> {
-        self.state.phase = Phase::End { winner };
+    async fn end(&mut self, winner: Option<Team>) -> Result<(), CoreError<PID>> {
+        let rogue_winners = self.rogue_winners(winner);
+        self.state.phase = Phase::End {
+            winner,
+            rogue_winners: rogue_winners.clone(),
+        };
+        self.cancel_timers();
+        let alive: Vec<PID> = self.sorted_players().iter().map(|&(k, _)| k).collect();
+        for &player in &alive {
+            self.state
+                .stats
+                .days_survived
+                .insert(player, self.state.day_no);
+        }
         self.inter
             .send(Event::End {
                 winner,
-                alive: self.state.players.iter().map(|(k, _)| *k).collect(),
-                role_history: self.state.role_history.clone(),
+                rogue_winners,
+                alive,
+                stats: Box::new(self.state.stats.clone()),
             })
             .await?;
         // self.inter.send(Event::Close).await?; // TODO: don't do this here?
         Ok(())
     }
 
+    // Rogue players, living or not, whose final role met its win condition
+    fn rogue_winners(&self, winner: Option<Team>) -> Vec<PID> {
+        let mut winners: Vec<PID> = self
+            .state
+            .stats
+            .role_history
+            .keys()
+            .filter_map(|&player| Some((player, self.known_role(player)?)))
+            .filter(|(_, role)| role.team() == Team::Rogue)
+            .filter(|(player, role)| role.is_winner(*player, winner, &self.state.players))
+            .map(|(player, _)| player)
+            .collect();
+        winners.sort();
+        winners
+    }
+
     async fn check_refocus(&mut self, player: PID, proxy: PID) -> Result<(), CoreError<PID>> {
         // Check contracting roles
         let mut updates: Vec<(PID, Role<PID>)> = Vec::new();
-        for (&contractor, &role) in &self.state.players {
+        for (contractor, role) in self.sorted_players() {
             if let Some(charge) = role.contract() {
                 if charge == player {
                     let new_role = match role {
@@ -802,11 +1570,13 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
     async fn refocus(&mut self, player: PID, role: Role<PID>) -> Result<(), CoreError<PID>> {
         let former_role = Self::validate_player(&self.state.players, player)?;
         self.state.players.insert(player, role);
+        let modifiers = self.state.modifiers_of(player);
         self.state
+            .stats
             .role_history
             .entry(player)
             .or_insert(Vec::new())
-            .push(role);
+            .push(RoleRecord { role, modifiers });
         self.inter
             .send(Event::Refocus {
                 player,
@@ -820,12 +1590,14 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
     async fn to_day(
         &mut self,
         blocks: Option<HashMap<PID, Vec<PID>>>,
+        stolen: HashMap<PID, PID>,
     ) -> Result<(), CoreError<PID>> {
         self.state.day_no += 1;
         let blocks = blocks.unwrap_or(HashMap::new());
         self.state.phase = Phase::Day {
             votes: HashMap::new(),
             blocks,
+            stolen,
         };
         self.state.timer = None;
         self.inter
@@ -833,13 +1605,15 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
                 day_no: self.state.day_no,
             })
             .await?;
+        self.set_deadline(self.rules.timer_rules.day_time).await?;
         Ok(())
     }
 
     async fn to_night(&mut self) -> Result<(), CoreError<PID>> {
         self.state.phase = Phase::Night {
             targets: HashMap::new(),
-            scheme: None,
+            schemes: HashMap::new(),
+            picks: HashMap::new(),
         };
         self.state.timer = None;
         self.inter
@@ -847,6 +1621,7 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
                 day_no: self.state.day_no,
             })
             .await?;
+        self.set_deadline(self.rules.timer_rules.night_time).await?;
         Ok(())
     }
 
@@ -861,7 +1636,7 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
             hammer,
             options: options.clone(),
         };
-        self.state.timer = None;
+        self.cancel_timers();
         self.inter
             .send(Event::Eclipse {
                 avenger,
@@ -872,6 +1647,106 @@ impl<PID: ID, GID: ID> Core<PID, GID> {
         Ok(())
     }
 
+    // Starts the deadline for the current phase, or clears it if there is no time limit
+    async fn set_deadline(&mut self, duration: Option<Duration>) -> Result<(), CoreError<PID>> {
+        let Some(duration) = duration else {
+            self.state.deadline = None;
+            return Ok(());
+        };
+        let end_time = self.action_time + duration;
+        self.state.deadline = Some(Timer {
+            end_time,
+            data: Action::Deadline,
+        });
+        self.inter
+            .send(Event::Deadline {
+                phase: self.state.phase.kind(),
+                end_time,
+            })
+            .await?;
+        Ok(())
+    }
+
+    // Living players in a fixed order, so events built from them are reproducible
+    fn sorted_players(&self) -> Vec<(PID, Role<PID>)> {
+        let mut players: Vec<_> = self.state.players.iter().map(|(&p, &r)| (p, r)).collect();
+        players.sort_by_key(|&(player, _)| player);
+        players
+    }
+
+    // Current role of a living player, or the last known role of an eliminated one
+    fn known_role(&self, player: PID) -> Option<Role<PID>> {
+        match self.state.players.get(&player) {
+            Some(&role) => Some(role),
+            None => self.state.stats.last_role(player),
+        }
+    }
+
+    // The weight of every living player's vote today. A stolen vote counts for its thief instead.
+    fn vote_weights(&self) -> HashMap<PID, i32> {
+        let base = |player: &PID| self.state.vote_weights.get(player).copied().unwrap_or(1);
+        let mut weights: HashMap<PID, i32> =
+            self.state.players.keys().map(|p| (*p, base(p))).collect();
+        if let Phase::Day { stolen, .. } = &self.state.phase {
+            for (victim, thief) in stolen {
+                let vote = base(victim);
+                if let Some(weight) = weights.get_mut(victim) {
+                    *weight = 0;
+                }
+                if let Some(weight) = weights.get_mut(thief) {
+                    *weight += vote;
+                }
+            }
+        }
+        weights
+    }
+
+    // Whether the player's role has a night action they can still use
+    fn can_target(modifiers: &HashMap<PID, Vec<Modifier>>, player: PID, role: &Role<PID>) -> bool {
+        let used = modifiers
+            .get(&player)
+            .is_some_and(|modifiers| modifiers.contains(&Modifier::OneShot(true)));
+        role.is_targeting() && !used
+    }
+
+    // Whether a night can't dawn until the player picks a target. Players out of charges don't have to.
+    fn awaits_target(
+        modifiers: &HashMap<PID, Vec<Modifier>>,
+        charges: &HashMap<PID, u8>,
+        player: PID,
+        role: &Role<PID>,
+    ) -> bool {
+        Self::can_target(modifiers, player, role) && charges.get(&player) != Some(&0)
+    }
+
+    // Players who know each other's identities: each kill faction, and the masons
+    fn teammates(&self, player: PID, role: Role<PID>) -> Vec<PID> {
+        let knows = |other: Role<PID>| match role {
+            Role::MASON => other == Role::MASON,
+            _ => role.team().is_faction() && other.team() == role.team(),
+        };
+        self.sorted_players()
+            .into_iter()
+            .filter(|&(other, other_role)| other != player && knows(other_role))
+            .map(|(other, _)| other)
+            .collect()
+    }
+
+    // What a living player can see of the game
+    fn player_view(&self, player: PID) -> Result<PlayerView<PID>, CoreError<PID>> {
+        let role = Self::validate_player(&self.state.players, player)?;
+        Ok(PlayerView {
+            player,
+            day_no: self.state.day_no,
+            phase: self.state.phase.kind(),
+            role: role.as_known(),
+            modifiers: self.state.modifiers_of(player),
+            charges: self.state.charges.get(&player).copied(),
+            teammates: self.teammates(player, role),
+            players: self.sorted_players().into_iter().map(|(p, _)| p).collect(),
+        })
+    }
+
     fn validate_player(
         players: &HashMap<PID, Role<PID>>,
         player: PID,
@@ -0,0 +1,235 @@
+// Role definitions loaded from data. Each definition becomes a Role::CUSTOM, built from
+//   the same dawn behaviors the built in roles use, so new roles don't need a recompile.
+
+use crate::base::ID;
+use crate::roles::{ActionType, CustomRole, Modifier, Role, Team};
+use crate::scripting;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoleDefinition {
+    pub name: String,
+    pub team: Team,
+    #[serde(default)]
+    pub action: ActionType,
+    // Dawn priority. The built in priority for the action if unset
+    #[serde(default)]
+    pub priority: Option<i8>,
+    // Given to every player with this role at the start of the game
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+    #[serde(default)]
+    pub description: String,
+    // The night action for the script action type, see scripting
+    #[serde(default)]
+    pub script: Option<String>,
+}
+
+// A TOML file of role definitions, as [[roles]] tables
+#[derive(Debug, Deserialize)]
+struct RoleFile {
+    #[serde(default)]
+    roles: Vec<RoleDefinition>,
+}
+
+#[derive(Debug)]
+pub enum RoleDefError {
+    IoError(io::Error),
+    TomlError(toml::de::Error),
+    DuplicateName { name: String },
+    TooManyRoles { count: usize },
+    MissingScript { name: String },
+    ScriptError { name: String, error: String },
+}
+
+impl RoleDefinition {
+    // The role for this definition, given its place in the rules' role_definitions
+    pub fn role<PID>(&self, id: u16) -> Role<PID> {
+        Role::CUSTOM(CustomRole {
+            id,
+            team: self.team,
+            action: self.action,
+            priority: self.priority.unwrap_or(self.action.default_priority()),
+        })
+    }
+}
+
+pub fn from_toml(toml_str: &str) -> Result<Vec<RoleDefinition>, RoleDefError> {
+    let file: RoleFile = toml::from_str(toml_str)?;
+    validate(&file.roles)?;
+    Ok(file.roles)
+}
+
+// Load every .toml file in a directory. Files are read in name order, so ids stay stable.
+pub fn load_dir(dir: impl AsRef<Path>) -> Result<Vec<RoleDefinition>, RoleDefError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut definitions = Vec::new();
+    for path in paths {
+        let file: RoleFile = toml::from_str(&fs::read_to_string(path)?)?;
+        definitions.extend(file.roles);
+    }
+    validate(&definitions)?;
+    Ok(definitions)
+}
+
+// Names must be unique, ids must fit in a CustomRole, and scripts must compile
+pub fn validate(definitions: &[RoleDefinition]) -> Result<(), RoleDefError> {
+    if definitions.len() > u16::MAX as usize {
+        return Err(RoleDefError::TooManyRoles {
+            count: definitions.len(),
+        });
+    }
+    for (i, definition) in definitions.iter().enumerate() {
+        if definitions[..i].iter().any(|d| d.name == definition.name) {
+            return Err(RoleDefError::DuplicateName {
+                name: definition.name.clone(),
+            });
+        }
+        match &definition.script {
+            Some(script) => {
+                scripting::compile(script).map_err(|error| RoleDefError::ScriptError {
+                    name: definition.name.clone(),
+                    error,
+                })?;
+            }
+            None if definition.action == ActionType::Script => {
+                return Err(RoleDefError::MissingScript {
+                    name: definition.name.clone(),
+                });
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+// The role with the given name, if it is defined
+pub fn find<PID>(definitions: &[RoleDefinition], name: &str) -> Option<Role<PID>> {
+    let id = definitions.iter().position(|d| d.name == name)?;
+    Some(definitions[id].role(id as u16))
+}
+
+// The definition a custom role was built from
+pub fn definition<'a, PID>(
+    definitions: &'a [RoleDefinition],
+    role: &Role<PID>,
+) -> Option<&'a RoleDefinition> {
+    match role {
+        Role::CUSTOM(custom) => definitions.get(custom.id as usize),
+        _ => None,
+    }
+}
+
+// Starting modifiers for every player with a custom role. Core::new gives them to the players
+pub fn modifiers<PID: ID>(
+    definitions: &[RoleDefinition],
+    roles: &HashMap<PID, Role<PID>>,
+) -> HashMap<PID, Vec<Modifier>> {
+    roles
+        .iter()
+        .filter_map(|(&player, role)| {
+            let definition = definition(definitions, role)?;
+            (!definition.modifiers.is_empty()).then(|| (player, definition.modifiers.clone()))
+        })
+        .collect()
+}
+
+impl From<io::Error> for RoleDefError {
+    fn from(e: io::Error) -> Self {
+        RoleDefError::IoError(e)
+    }
+}
+
+impl From<toml::de::Error> for RoleDefError {
+    fn from(e: toml::de::Error) -> Self {
+        RoleDefError::TomlError(e)
+    }
+}
+
+mod test {
+
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_roledefs_toml() {
+        let toml_str = r#"
+        [[roles]]
+        name = "SEER"
+        team = "Town"
+        action = "investigate"
+        description = "Learns a player's role each night"
+
+        [[roles]]
+        name = "BOUNCER"
+        team = "Town"
+        action = "block"
+        priority = 3
+        modifiers = ["block_immune", { one_shot = false }]
+
+        [[roles]]
+        name = "CONSIGLIERE"
+        team = "Mafia"
+        "#;
+
+        let definitions = from_toml(toml_str).unwrap();
+        assert_eq!(definitions.len(), 3);
+        assert_eq!(
+            find::<u32>(&definitions, "SEER"),
+            Some(Role::CUSTOM(CustomRole {
+                id: 0,
+                team: Team::Town,
+                action: ActionType::Investigate,
+                priority: -1,
+            }))
+        );
+        let bouncer = find::<u32>(&definitions, "BOUNCER").unwrap();
+        assert_eq!(bouncer.night_action_priority(&Default::default()), Some(3));
+        assert_eq!(
+            definition(&definitions, &bouncer).unwrap().modifiers,
+            vec![Modifier::BlockImmune, Modifier::OneShot(false)]
+        );
+        let consigliere = find::<u32>(&definitions, "CONSIGLIERE").unwrap();
+        assert_eq!(consigliere.team(), Team::Mafia);
+        assert!(!consigliere.is_targeting());
+        assert_eq!(find::<u32>(&definitions, "COP"), None);
+
+        let toml_str = r#"
+        [[roles]]
+        name = "SEER"
+        team = "Town"
+
+        [[roles]]
+        name = "SEER"
+        team = "Mafia"
+        "#;
+        assert!(matches!(
+            from_toml(toml_str),
+            Err(RoleDefError::DuplicateName { name }) if name == "SEER"
+        ));
+
+        let toml_str = r#"
+        [[roles]]
+        name = "JAILER"
+        team = "Town"
+        action = "script"
+        "#;
+        assert!(matches!(
+            from_toml(toml_str),
+            Err(RoleDefError::MissingScript { name }) if name == "JAILER"
+        ));
+    }
+}
@@ -0,0 +1,455 @@
+// Rolegen engine. Builds a role assignment for a set of players from the RolegenRules.
+
+use crate::base::ID;
+use crate::roledefs::{self, RoleDefinition};
+use crate::roles::{Role, RoleKind, Sanity, Team};
+use crate::rules::{RoleOption, RolegenRules};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RolegenError {
+    InvalidRatio {
+        mafia_ratio: f64,
+        rogue_ratio: f64,
+    },
+    UnbalancedTeams {
+        players: usize,
+        mafia: usize,
+    },
+    DuplicateRole {
+        role: RoleKind,
+    },
+    UnknownCustomRole {
+        name: String,
+    },
+    UnsupportedTeam {
+        name: String,
+        team: Team,
+    },
+    InvalidLimits {
+        role: RoleKind,
+    },
+    MinimumsExceedSlots {
+        team: Team,
+        required: usize,
+        slots: usize,
+    },
+    NotEnoughRoles {
+        team: Team,
+        missing: usize,
+    },
+    NoContractTarget {
+        player_count: usize,
+        role: RoleKind,
+    },
+}
+
+// Generate roles with a seeded rng, or a random seed if none is given.
+// Custom role options are built from the given definitions.
+pub fn generate<PID: ID>(
+    players: &[PID],
+    rules: &RolegenRules,
+    definitions: &[RoleDefinition],
+    seed: Option<u64>,
+) -> Result<HashMap<PID, Role<PID>>, RolegenError> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    generate_with_rng(players, rules, definitions, &mut rng)
+}
+
+pub fn generate_with_rng<PID: ID, R: Rng + ?Sized>(
+    players: &[PID],
+    rules: &RolegenRules,
+    definitions: &[RoleDefinition],
+    rng: &mut R,
+) -> Result<HashMap<PID, Role<PID>>, RolegenError> {
+    let (n_mafia, n_rogue) = team_sizes(players.len(), rules)?;
+    let n_town = players.len() - n_mafia - n_rogue;
+
+    for (i, option) in rules.roles.iter().enumerate() {
+        if rules.roles[..i]
+            .iter()
+            .any(|o| o.role == option.role && o.name == option.name)
+        {
+            return Err(RolegenError::DuplicateRole { role: option.role });
+        }
+    }
+
+    // Custom options are resolved up front, they carry their own team
+    let mut customs: Vec<Option<Role<PID>>> = Vec::with_capacity(rules.roles.len());
+    for option in &rules.roles {
+        let custom = match option.role {
+            RoleKind::CUSTOM => {
+                let name = option.name.clone().unwrap_or_default();
+                let Some(role) = roledefs::find(definitions, &name) else {
+                    return Err(RolegenError::UnknownCustomRole { name });
+                };
+                // Only the town, mafia and rogue teams have slots to fill
+                let team = role.team();
+                if !matches!(team, Team::Town | Team::Mafia | Team::Rogue) {
+                    return Err(RolegenError::UnsupportedTeam { name, team });
+                }
+                Some(role)
+            }
+            _ => None,
+        };
+        customs.push(custom);
+    }
+    let teams: Vec<Team> = rules
+        .roles
+        .iter()
+        .zip(&customs)
+        .map(|(option, custom)| match custom {
+            Some(role) => role.team(),
+            None => option
+                .role
+                .team()
+                .expect("Built in roles have a fixed team"),
+        })
+        .collect();
+
+    let mut picks = Vec::with_capacity(players.len());
+    picks.extend(fill_team(Team::Town, n_town, &rules.roles, &teams, rng)?);
+    picks.extend(fill_team(Team::Mafia, n_mafia, &rules.roles, &teams, rng)?);
+    picks.extend(fill_team(Team::Rogue, n_rogue, &rules.roles, &teams, rng)?);
+
+    let mut shuffled = players.to_vec();
+    shuffled.shuffle(rng);
+    let assignment: Vec<(PID, usize)> = shuffled.into_iter().zip(picks).collect();
+
+    // Contracts are drawn from players outside the rogue team
+    let charges: Vec<PID> = assignment
+        .iter()
+        .filter(|&&(_, i)| teams[i] != Team::Rogue)
+        .map(|(player, _)| *player)
+        .collect();
+
+    let mut roles = HashMap::new();
+    for &(player, i) in &assignment {
+        if let Some(role) = customs[i] {
+            roles.insert(player, role);
+            continue;
+        }
+        let kind = rules.roles[i].role;
+        let charge = match kind {
+            RoleKind::GUARD | RoleKind::AGENT => {
+                let options: Vec<PID> = charges.iter().copied().filter(|&c| c != player).collect();
+                let Some(&charge) = options.choose(rng) else {
+                    return Err(RolegenError::NoContractTarget {
+                        player_count: players.len(),
+                        role: kind,
+                    });
+                };
+                charge
+            }
+            _ => PID::default(),
+        };
+        let role = match Role::from_kind(kind, charge) {
+            Some(Role::COP(_)) => Role::COP(pick_sanity(rules, rng)),
+            Some(role) => role,
+            None => unreachable!("Custom roles are built from their definition"),
+        };
+        roles.insert(player, role);
+    }
+    Ok(roles)
+}
+
+// Draw a COP sanity by weight. Sane if there are no sanities to draw from
+fn pick_sanity<R: Rng + ?Sized>(rules: &RolegenRules, rng: &mut R) -> Sanity {
+    rules
+        .cop_sanities
+        .choose_weighted(rng, |option| option.weight)
+        .map_or(Sanity::Sane, |option| option.sanity)
+}
+
+// Number of (mafia, rogue) players for a game of n players
+fn team_sizes(n: usize, rules: &RolegenRules) -> Result<(usize, usize), RolegenError> {
+    let mafia_ratio = rules.mafia_ratio;
+    let rogue_ratio = rules.rogue_ratio;
+    let valid = |r: f64| (0.0..=1.0).contains(&r);
+    if !valid(mafia_ratio) || !valid(rogue_ratio) || mafia_ratio + rogue_ratio > 1.0 {
+        return Err(RolegenError::InvalidRatio {
+            mafia_ratio,
+            rogue_ratio,
+        });
+    }
+
+    let n_mafia = ((n as f64 * mafia_ratio).round() as usize).max(1);
+    let n_rogue = (n as f64 * rogue_ratio).floor() as usize;
+
+    // The game is over immediately if mafia are not outnumbered
+    if n_mafia + n_rogue > n || n - n_mafia <= n_mafia {
+        return Err(RolegenError::UnbalancedTeams {
+            players: n,
+            mafia: n_mafia,
+        });
+    }
+    Ok((n_mafia, n_rogue))
+}
+
+// Pick role kinds for the slots of one team. Minimums are placed first,
+//   the rest are drawn by weight from roles that are not at their maximum.
+// Grouped roles fill a whole group of slots each time they are picked.
+// Returns the index in the pool of the option picked for each slot.
+fn fill_team<R: Rng + ?Sized>(
+    team: Team,
+    slots: usize,
+    pool: &[RoleOption],
+    teams: &[Team],
+    rng: &mut R,
+) -> Result<Vec<usize>, RolegenError> {
+    let indices: Vec<usize> = (0..pool.len()).filter(|&i| teams[i] == team).collect();
+    let options: Vec<&RoleOption> = indices.iter().map(|&i| &pool[i]).collect();
+
+    if let Some(option) = options
+        .iter()
+        .find(|o| o.max.is_some_and(|max| o.min > max))
+    {
+        return Err(RolegenError::InvalidLimits { role: option.role });
+    }
+
+    let group = |i: usize| options[i].group.max(1);
+    let required: usize = (0..options.len()).map(|i| options[i].min * group(i)).sum();
+    if required > slots {
+        return Err(RolegenError::MinimumsExceedSlots {
+            team,
+            required,
+            slots,
+        });
+    }
+
+    let mut counts: Vec<usize> = options.iter().map(|o| o.min).collect();
+
+    let mut filled = required;
+    while filled < slots {
+        let available: Vec<usize> = (0..options.len())
+            .filter(|&i| options[i].weight > 0 && counts[i] < options[i].max.unwrap_or(slots))
+            .filter(|&i| group(i) <= slots - filled)
+            .collect();
+        let Ok(&i) = available.choose_weighted(rng, |&i| options[i].weight) else {
+            return Err(RolegenError::NotEnoughRoles {
+                team,
+                missing: slots - filled,
+            });
+        };
+        counts[i] += 1;
+        filled += group(i);
+    }
+
+    let mut picks = Vec::with_capacity(slots);
+    for (i, count) in counts.into_iter().enumerate() {
+        picks.extend(std::iter::repeat_n(indices[i], count * group(i)));
+    }
+    Ok(picks)
+}
+
+mod test {
+
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::rules::SanityOption;
+
+    #[test]
+    fn test_rolegen_default() {
+        let players: Vec<u32> = (1..=9).collect();
+        let rules = RolegenRules::default();
+        let roles = generate(&players, &rules, &[], Some(7)).unwrap();
+
+        assert_eq!(roles.len(), 9);
+        let n_mafia = roles.values().filter(|r| r.team() == Team::Mafia).count();
+        assert_eq!(n_mafia, 2);
+
+        // Same seed, same roles
+        assert_eq!(roles, generate(&players, &rules, &[], Some(7)).unwrap());
+    }
+
+    #[test]
+    fn test_rolegen_limits() {
+        let players: Vec<u32> = (1..=8).collect();
+        let rules = RolegenRules {
+            mafia_ratio: 0.25,
+            rogue_ratio: 0.25,
+            roles: vec![
+                RoleOption::new(RoleKind::TOWN, 1, 0, None),
+                RoleOption::new(RoleKind::COP, 1, 1, Some(1)),
+                RoleOption::new(RoleKind::MAFIA, 1, 0, None),
+                RoleOption::new(RoleKind::GUARD, 1, 2, Some(2)),
+            ],
+            cop_sanities: vec![
+                SanityOption::new(Sanity::Naive, 1),
+                SanityOption::new(Sanity::Paranoid, 0),
+            ],
+        };
+        for seed in 0..20 {
+            let roles = generate(&players, &rules, &[], Some(seed)).unwrap();
+            let count = |kind| roles.values().filter(|r| r.kind() == kind).count();
+            assert_eq!(count(RoleKind::COP), 1);
+            assert_eq!(count(RoleKind::TOWN), 3);
+            assert_eq!(count(RoleKind::MAFIA), 2);
+            assert_eq!(count(RoleKind::GUARD), 2);
+            assert!(roles.values().any(|r| *r == Role::COP(Sanity::Naive)));
+            for (player, role) in &roles {
+                if let Some(charge) = role.contract() {
+                    assert_ne!(charge, *player);
+                    assert_ne!(roles[&charge].team(), Team::Rogue);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rolegen_groups() {
+        let players: Vec<u32> = (1..=8).collect();
+        let rules = RolegenRules {
+            mafia_ratio: 0.25,
+            rogue_ratio: 0.0,
+            roles: vec![
+                RoleOption::new(RoleKind::TOWN, 1, 0, None),
+                RoleOption::new(RoleKind::MASON, 1, 1, Some(1)).with_group(3),
+                RoleOption::new(RoleKind::MAFIA, 1, 0, None),
+            ],
+            cop_sanities: vec![],
+        };
+        for seed in 0..20 {
+            let roles = generate(&players, &rules, &[], Some(seed)).unwrap();
+            let count = |kind| roles.values().filter(|r| r.kind() == kind).count();
+            assert_eq!(count(RoleKind::MASON), 3);
+            assert_eq!(count(RoleKind::TOWN), 3);
+        }
+
+        // A group that doesn't fit the slots left is never picked
+        let rules = RolegenRules {
+            roles: vec![
+                RoleOption::new(RoleKind::MASON, 1, 0, None).with_group(4),
+                RoleOption::new(RoleKind::MAFIA, 1, 0, None),
+            ],
+            ..rules
+        };
+        assert_eq!(
+            generate(&players, &rules, &[], Some(0)),
+            Err(RolegenError::NotEnoughRoles {
+                team: Team::Town,
+                missing: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_rolegen_custom() {
+        let players: Vec<u32> = (1..=8).collect();
+        let definitions = roledefs::from_toml(
+            r#"
+            [[roles]]
+            name = "SEER"
+            team = "Town"
+            action = "investigate"
+
+            [[roles]]
+            name = "HITMAN"
+            team = "Mafia"
+
+            [[roles]]
+            name = "SERIAL_KILLER"
+            team = "Killer"
+            "#,
+        )
+        .unwrap();
+        let mut rules = RolegenRules {
+            mafia_ratio: 0.25,
+            rogue_ratio: 0.0,
+            roles: vec![
+                RoleOption::new(RoleKind::TOWN, 1, 0, None),
+                RoleOption::custom("SEER", 1, 1, Some(1)),
+                RoleOption::custom("HITMAN", 1, 2, None),
+            ],
+            cop_sanities: vec![],
+        };
+        let seer = roledefs::find(&definitions, "SEER").unwrap();
+        let hitman = roledefs::find(&definitions, "HITMAN").unwrap();
+        for seed in 0..20 {
+            let roles = generate(&players, &rules, &definitions, Some(seed)).unwrap();
+            assert_eq!(roles.values().filter(|&&r| r == seer).count(), 1);
+            assert_eq!(roles.values().filter(|&&r| r == hitman).count(), 2);
+        }
+
+        // Rival factions aren't given slots, so they can't be rolled
+        rules
+            .roles
+            .push(RoleOption::custom("SERIAL_KILLER", 1, 0, None));
+        assert_eq!(
+            generate(&players, &rules, &definitions, Some(0)),
+            Err(RolegenError::UnsupportedTeam {
+                name: "SERIAL_KILLER".to_string(),
+                team: Team::Killer,
+            })
+        );
+
+        rules.roles.pop();
+        rules.roles.push(RoleOption::custom("SHAMAN", 1, 0, None));
+        assert_eq!(
+            generate(&players, &rules, &definitions, Some(0)),
+            Err(RolegenError::UnknownCustomRole {
+                name: "SHAMAN".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_rolegen_errors() {
+        let players: Vec<u32> = (1..=4).collect();
+        let mut rules = RolegenRules {
+            mafia_ratio: 0.5,
+            ..RolegenRules::default()
+        };
+        assert_eq!(
+            generate(&players, &rules, &[], Some(0)),
+            Err(RolegenError::UnbalancedTeams {
+                players: 4,
+                mafia: 2
+            })
+        );
+
+        rules.mafia_ratio = 0.25;
+        rules.roles = vec![
+            RoleOption::new(RoleKind::COP, 1, 0, Some(1)),
+            RoleOption::new(RoleKind::MAFIA, 1, 0, None),
+        ];
+        assert_eq!(
+            generate(&players, &rules, &[], Some(0)),
+            Err(RolegenError::NotEnoughRoles {
+                team: Team::Town,
+                missing: 2
+            })
+        );
+
+        rules.roles = vec![
+            RoleOption::new(RoleKind::TOWN, 1, 0, None),
+            RoleOption::new(RoleKind::MAFIA, 1, 2, None),
+        ];
+        assert_eq!(
+            generate(&players, &rules, &[], Some(0)),
+            Err(RolegenError::MinimumsExceedSlots {
+                team: Team::Mafia,
+                required: 2,
+                slots: 1
+            })
+        );
+
+        rules
+            .roles
+            .push(RoleOption::new(RoleKind::TOWN, 1, 0, None));
+        assert_eq!(
+            generate(&players, &rules, &[], Some(0)),
+            Err(RolegenError::DuplicateRole {
+                role: RoleKind::TOWN
+            })
+        );
+    }
+}
@@ -5,32 +5,63 @@ use std::hash::Hash;
 
 use crate::core::base::{Choice, ID};
 use crate::interface::{CoreError, Event, EventTx};
+use crate::roledefs;
+use crate::rules::{InfoLevel, Rules, SaveDisclosure, StoppedKiller};
+use crate::scripting::{self, ScriptEffect};
+use crate::stats::NightOutcome;
+
+// Votes a MAYOR casts once revealed
+pub const MAYOR_VOTES: i32 = 2;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumKind, Serialize, Deserialize)]
-#[enum_kind(RoleKind, derive(Serialize, Deserialize))]
+#[enum_kind(RoleKind, derive(Hash, PartialOrd, Ord, Serialize, Deserialize))]
 pub enum Role<PID> {
     TOWN,
-    COP,
+    COP(Sanity),
     DOCTOR,
     CELEB,
+    VIGILANTE, // Shots are night action charges
+    MILLER,
+    TRACKER,
+    WATCHER,
+    MASON, // Knows the other masons
+    MAYOR, // Votes count double once revealed
     MAFIA,
     STRIPPER,
+    GODFATHER,
+    THIEF,       // Steals a player's vote for the next day
     IDIOT(bool), // Bool is if IDIOT was elected
     SURVIVOR,
     GUARD(PID),
     AGENT(PID),
+    CUSTOM(CustomRole), // Defined in data, see roledefs
 }
 
 impl<PID: ID> Role<PID> {
     pub fn is_targeting(&self) -> bool {
         match self {
-            Role::COP | Role::DOCTOR | Role::STRIPPER => true,
+            Role::COP(_)
+            | Role::DOCTOR
+            | Role::STRIPPER
+            | Role::TRACKER
+            | Role::WATCHER
+            | Role::THIEF
+            | Role::VIGILANTE => true,
+            Role::CUSTOM(custom) => custom.action != ActionType::None,
             _ => false,
         }
     }
 
+    // Night action charges the role starts the game with. None is no limit
+    pub fn starting_charges(&self, rules: &Rules) -> Option<u8> {
+        match self {
+            Role::VIGILANTE => Some(rules.night_rules.vigilante_shots),
+            _ => None,
+        }
+    }
+
     pub fn is_scheming(&self) -> bool {
-        return self.team() == Team::Mafia;
+        return self.team().is_faction();
     }
 
     pub fn contract(&self) -> Option<PID> {
@@ -40,34 +71,248 @@ impl<PID: ID> Role<PID> {
             _ => None,
         };
     }
-    pub fn priority(&self) -> Option<i8> {
-        return match self {
-            Role::COP => Some(-1),
-            Role::DOCTOR => Some(1),
-            Role::STRIPPER => Some(2),
-            _ => None,
-        };
-    }
 
     pub fn team(&self) -> Team {
-        return match self {
-            Role::TOWN | Role::COP | Role::DOCTOR | Role::CELEB => Team::Town,
-            Role::MAFIA | Role::STRIPPER => Team::Mafia,
-            Role::IDIOT(_) | Role::SURVIVOR | Role::GUARD(_) | Role::AGENT(_) => Team::Rogue,
-        };
+        if let Role::CUSTOM(custom) = self {
+            return custom.team;
+        }
+        self.kind()
+            .team()
+            .expect("Built in roles have a fixed team")
+    }
+
+    // What the role does at dawn, if anything
+    pub fn action_type(&self) -> ActionType {
+        match self {
+            Role::COP(_) => ActionType::Investigate,
+            Role::DOCTOR => ActionType::Save,
+            Role::STRIPPER => ActionType::Block,
+            Role::VIGILANTE => ActionType::Kill,
+            Role::TRACKER => ActionType::Track,
+            Role::WATCHER => ActionType::Watch,
+            Role::THIEF => ActionType::Steal,
+            Role::CUSTOM(custom) => custom.action,
+            _ => ActionType::None,
+        }
+    }
+
+    // The role a player appears to have when investigated
+    pub fn apparent_role(&self) -> Role<PID> {
+        match self {
+            Role::GODFATHER => Role::TOWN,
+            Role::MILLER => Role::MAFIA,
+            _ => *self,
+        }
+    }
+
+    // The team a player appears to be on when investigated
+    pub fn apparent_team(&self) -> Team {
+        self.apparent_role().team()
+    }
+
+    // The role as its player knows it. A COP believes they're sane until the end of the game.
+    pub fn as_known(&self) -> Role<PID> {
+        match self {
+            Role::COP(_) => Role::COP(Sanity::Sane),
+            _ => *self,
+        }
+    }
+
+    // Whether a player with this role wins, given the winning team (None for a draw) and the
+    // players left alive. Rogue roles win on their own conditions, alongside whichever team won.
+    pub fn is_winner(
+        &self,
+        player: PID,
+        winner: Option<Team>,
+        alive: &HashMap<PID, Role<PID>>,
+    ) -> bool {
+        match self {
+            Role::SURVIVOR => alive.contains_key(&player),
+            Role::IDIOT(elected) => *elected,
+            Role::GUARD(charge) => alive.contains_key(charge),
+            Role::AGENT(charge) => !alive.contains_key(charge),
+            _ => Some(self.team()) == winner,
+        }
     }
     pub fn kind(&self) -> RoleKind {
         return RoleKind::from(self);
     }
+
+    // Build a role of the given kind. Contract roles are given charge, other roles ignore it.
+    // None for custom roles, which are built from their definition.
+    pub fn from_kind(kind: RoleKind, charge: PID) -> Option<Self> {
+        let role = match kind {
+            RoleKind::TOWN => Role::TOWN,
+            RoleKind::COP => Role::COP(Sanity::Sane),
+            RoleKind::DOCTOR => Role::DOCTOR,
+            RoleKind::CELEB => Role::CELEB,
+            RoleKind::VIGILANTE => Role::VIGILANTE,
+            RoleKind::MILLER => Role::MILLER,
+            RoleKind::TRACKER => Role::TRACKER,
+            RoleKind::WATCHER => Role::WATCHER,
+            RoleKind::MASON => Role::MASON,
+            RoleKind::MAYOR => Role::MAYOR,
+            RoleKind::MAFIA => Role::MAFIA,
+            RoleKind::STRIPPER => Role::STRIPPER,
+            RoleKind::GODFATHER => Role::GODFATHER,
+            RoleKind::THIEF => Role::THIEF,
+            RoleKind::IDIOT => Role::IDIOT(false),
+            RoleKind::SURVIVOR => Role::SURVIVOR,
+            RoleKind::GUARD => Role::GUARD(charge),
+            RoleKind::AGENT => Role::AGENT(charge),
+            RoleKind::CUSTOM => return None,
+        };
+        Some(role)
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+impl RoleKind {
+    // None for custom roles, which carry their own team
+    pub fn team(&self) -> Option<Team> {
+        let team = match self {
+            RoleKind::TOWN
+            | RoleKind::COP
+            | RoleKind::DOCTOR
+            | RoleKind::CELEB
+            | RoleKind::VIGILANTE
+            | RoleKind::MILLER
+            | RoleKind::TRACKER
+            | RoleKind::WATCHER
+            | RoleKind::MASON
+            | RoleKind::MAYOR => Team::Town,
+            RoleKind::MAFIA | RoleKind::STRIPPER | RoleKind::GODFATHER | RoleKind::THIEF => {
+                Team::Mafia
+            }
+            RoleKind::IDIOT | RoleKind::SURVIVOR | RoleKind::GUARD | RoleKind::AGENT => Team::Rogue,
+            RoleKind::CUSTOM => return None,
+        };
+        Some(team)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Team {
     Town,
     Mafia,
+    Family, // A second mafia family
+    Killer, // A serial killer faction
     Rogue,
 }
 
+impl Team {
+    // Teams that meet at night and share a kill. Town wins once none are left.
+    pub fn is_faction(&self) -> bool {
+        matches!(self, Team::Mafia | Team::Family | Team::Killer)
+    }
+}
+
+// The dawn behaviors a role can have. Custom roles pick one of these.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionType {
+    #[default]
+    None,
+    Investigate,
+    Save,
+    Block,
+    Kill,
+    Track,
+    Watch,
+    Steal,
+    // Runs the script in the role's definition
+    Script,
+}
+
+impl ActionType {
+    // Priority the built in roles use for this action
+    pub fn default_priority(&self) -> i8 {
+        match self {
+            ActionType::None | ActionType::Kill | ActionType::Script => 0,
+            ActionType::Investigate | ActionType::Steal => -1,
+            ActionType::Save => 1,
+            ActionType::Block => 2,
+            ActionType::Track | ActionType::Watch => -2,
+        }
+    }
+}
+
+// A role built from a RoleDefinition. It carries everything dawn needs,
+//   the rest of the definition is found by id in the rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CustomRole {
+    pub id: u16,
+    pub team: Team,
+    pub action: ActionType,
+    pub priority: i8,
+}
+
+// How a COP's investigation results are skewed
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Sanity {
+    #[default]
+    Sane,
+    // Everyone looks innocent
+    Naive,
+    // Everyone looks guilty
+    Paranoid,
+    // Innocent and guilty are swapped
+    Insane,
+}
+
+impl Sanity {
+    // The result a cop of this sanity sees for a target's apparent role
+    pub fn skew<PID: ID>(&self, apparent: Role<PID>) -> Role<PID> {
+        let guilty = apparent.team().is_faction();
+        match self {
+            Sanity::Sane => apparent,
+            Sanity::Naive if guilty => Role::TOWN,
+            Sanity::Paranoid if !guilty => Role::MAFIA,
+            Sanity::Insane if guilty => Role::TOWN,
+            Sanity::Insane => Role::MAFIA,
+            _ => apparent,
+        }
+    }
+}
+
+// Passive traits layered on top of a player's role
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Modifier {
+    // Survives one kill, then is lost
+    Bulletproof,
+    // Blocks have no effect
+    BlockImmune,
+    // Investigations learn nothing
+    InvestigateImmune,
+    // Night action can only be used once. Bool is if it was used
+    OneShot(bool),
+}
+
+// What is revealed about a player's role, limited by an InfoLevel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoleInfo<PID> {
+    Role(Role<PID>),
+    Team(Team),
+    Mafia(bool),
+    Hidden,
+}
+
+impl<PID: ID> RoleInfo<PID> {
+    pub fn new(role: Role<PID>, level: InfoLevel) -> Self {
+        match level {
+            InfoLevel::Role => RoleInfo::Role(role.as_known()),
+            InfoLevel::Team => RoleInfo::Team(role.team()),
+            InfoLevel::Mafia => RoleInfo::Mafia(role.team().is_faction()),
+            InfoLevel::Nothing => RoleInfo::Hidden,
+        }
+    }
+}
+
 // Night action implementations
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NightAction<PID: ID> {
@@ -79,113 +324,644 @@ pub struct NightAction<PID: ID> {
 
 impl<PID: ID> PartialOrd for NightAction<PID> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.role
-            .night_action_priority()
-            .partial_cmp(&other.role.night_action_priority())
+        self.priority.partial_cmp(&other.priority)
     }
 }
 
 impl<PID: ID> Ord for NightAction<PID> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.role
-            .night_action_priority()
-            .cmp(&other.role.night_action_priority())
+        self.priority.cmp(&other.priority)
     }
 }
 
 impl<PID: ID> NightAction<PID> {
     pub async fn perform(
+        &self,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events: &EventTx<PID>,
+    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
+        match self.role.action_type() {
+            ActionType::Investigate => {
+                let sanity = match self.role {
+                    Role::COP(sanity) => sanity,
+                    _ => Sanity::Sane,
+                };
+                self.investigate(sanity, dawn_state, rules, events).await
+            }
+            ActionType::Save => self.save(dawn_state, events).await,
+            ActionType::Block => self.block(dawn_state, events).await,
+            ActionType::Kill => self.shoot(dawn_state, rules, events).await,
+            ActionType::Track => self.track(dawn_state, rules, events).await,
+            ActionType::Watch => self.watch(dawn_state, rules, events).await,
+            ActionType::Steal => self.steal(dawn_state, rules, events).await,
+            ActionType::Script => self.script(dawn_state, rules, events).await,
+            ActionType::None => Ok(vec![]),
+        }
+    }
+
+    // Learn the target's apparent role, skewed by the investigator's sanity
+    async fn investigate(
+        &self,
+        sanity: Sanity,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events: &EventTx<PID>,
+    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
+        let actor = self.actor;
+        let target = self.target;
+        // if the investigator was killed, do nothing
+        if dawn_state.killed.contains_key(&actor) {
+            return Ok(vec![DawnStateChange::outcome(actor, NightOutcome::Killed)]);
+        }
+        // if target was killed, do nothing
+        if dawn_state.killed.contains_key(&target) {
+            return Ok(vec![DawnStateChange::outcome(
+                actor,
+                NightOutcome::TargetKilled,
+            )]);
+        }
+        if let Some(blockers) = dawn_state.blockers_info(actor, rules) {
+            events
+                .send(Event::EvidentBlock {
+                    blocked: actor,
+                    blockers,
+                })
+                .await?;
+            return Ok(vec![DawnStateChange::outcome(actor, NightOutcome::Blocked)]);
+        }
+        let result = Self::investigation_result(sanity, target, dawn_state, rules);
+        events
+            .send(Event::Investigate {
+                actor,
+                target,
+                result,
+            })
+            .await?;
+        Ok(vec![
+            DawnStateChange::Investigate {
+                actor,
+                target,
+                result,
+            },
+            DawnStateChange::Visit { actor, target },
+        ])
+    }
+
+    // What an investigation of the target learns
+    fn investigation_result(
+        sanity: Sanity,
+        target: PID,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+    ) -> RoleInfo<PID> {
+        if dawn_state.has_modifier(target, Modifier::InvestigateImmune) {
+            return RoleInfo::Hidden;
+        }
+        let apparent_role = dawn_state.players[&target].apparent_role();
+        RoleInfo::new(sanity.skew(apparent_role), rules.info_rules.investigation)
+    }
+
+    // Protect the target from kills tonight
+    async fn save(
         &self,
         dawn_state: &DawnState<PID>,
         events: &EventTx<PID>,
     ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
         let actor = self.actor;
         let target = self.target;
-        match self.role {
-            Role::COP => {
-                // if cop was killed, do nothing
-                if dawn_state.killed.contains_key(&actor) {
-                    return Ok(vec![]);
+        events.send(Event::Save { actor, target }).await?;
+        let mut changes = vec![DawnStateChange::Save { actor, target }];
+        // A blocked save still goes ahead, the block is revealed if the save is needed
+        if dawn_state.blocks.contains_key(&actor) {
+            changes.push(DawnStateChange::outcome(actor, NightOutcome::Blocked));
+        } else {
+            changes.push(DawnStateChange::Visit { actor, target });
+        }
+        Ok(changes)
+    }
+
+    // Stop the target's night action
+    async fn block(
+        &self,
+        dawn_state: &DawnState<PID>,
+        events: &EventTx<PID>,
+    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
+        let actor = self.actor;
+        let target = self.target;
+        if dawn_state.has_modifier(target, Modifier::BlockImmune) {
+            return Ok(vec![DawnStateChange::Visit { actor, target }]);
+        }
+        events.send(Event::Block { actor, target }).await?;
+        Ok(vec![
+            DawnStateChange::Block { actor, target },
+            DawnStateChange::Visit { actor, target },
+        ])
+    }
+
+    // Kill the target, unless they are saved
+    async fn shoot(
+        &self,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events: &EventTx<PID>,
+    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
+        let actor = self.actor;
+        let target = self.target;
+        if let Some(outcome) = Self::check_stopped(actor, dawn_state, rules, events).await? {
+            return Ok(vec![DawnStateChange::outcome(actor, outcome)]);
+        }
+        Self::attempt_shot(actor, target, dawn_state, rules, events).await
+    }
+
+    // A shot at the mark, which can be stopped by a save or by being bulletproof
+    async fn attempt_shot(
+        actor: PID,
+        mark: PID,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events: &EventTx<PID>,
+    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
+        let (mut changes, saved) = Self::check_saves(mark, dawn_state, rules, events).await?;
+        changes.push(DawnStateChange::Visit {
+            actor,
+            target: mark,
+        });
+        if saved {
+            changes.push(DawnStateChange::outcome(actor, NightOutcome::Saved));
+        } else if Self::check_bulletproof(mark, dawn_state, events).await? {
+            changes.push(DawnStateChange::Absorb { mark });
+            changes.push(DawnStateChange::outcome(actor, NightOutcome::Survived));
+        } else {
+            events.send(Event::Shoot { actor, mark }).await?;
+            changes.push(DawnStateChange::Kill {
+                killer: actor,
+                mark,
+            });
+        }
+        Ok(changes)
+    }
+
+    // Run the script from the role's definition. Its effects go through the same checks
+    //   as the built in actions, and a failed script only fails this action.
+    async fn script(
+        &self,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events: &EventTx<PID>,
+    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
+        let actor = self.actor;
+        if let Some(outcome) = Self::check_stopped(actor, dawn_state, rules, events).await? {
+            return Ok(vec![DawnStateChange::outcome(actor, outcome)]);
+        }
+        let Some(script) = roledefs::definition(&rules.role_definitions, &self.role)
+            .and_then(|definition| definition.script.as_deref())
+        else {
+            return Ok(vec![]);
+        };
+        let effects = match scripting::run(script, actor, self.target, dawn_state, rules) {
+            Ok(effects) => effects,
+            Err(error) => {
+                events.send(Event::ScriptFailed { actor, error }).await?;
+                return Ok(vec![DawnStateChange::outcome(actor, NightOutcome::Failed)]);
+            }
+        };
+
+        let mut changes = Vec::new();
+        for effect in effects {
+            match effect {
+                ScriptEffect::Block(target) => {
+                    if !dawn_state.has_modifier(target, Modifier::BlockImmune) {
+                        events.send(Event::Block { actor, target }).await?;
+                        changes.push(DawnStateChange::Block { actor, target });
+                    }
+                    changes.push(DawnStateChange::Visit { actor, target });
+                }
+                ScriptEffect::Save(target) => {
+                    events.send(Event::Save { actor, target }).await?;
+                    changes.push(DawnStateChange::Save { actor, target });
+                    changes.push(DawnStateChange::Visit { actor, target });
                 }
-                // if target was killed, do nothing
-                if dawn_state.killed.contains_key(&target) {
-                    return Ok(vec![]);
+                ScriptEffect::Kill(mark) => {
+                    changes
+                        .extend(Self::attempt_shot(actor, mark, dawn_state, rules, events).await?);
                 }
-                if let Some((&blocked, blockers)) = dawn_state.blocks.get_key_value(&actor) {
-                    let blockers = blockers.clone();
+                ScriptEffect::Investigate(target) => {
+                    let result =
+                        Self::investigation_result(Sanity::Sane, target, dawn_state, rules);
                     events
-                        .send(Event::EvidentBlock { blocked, blockers })
+                        .send(Event::Investigate {
+                            actor,
+                            target,
+                            result,
+                        })
                         .await?;
-                    return Ok(vec![]);
+                    changes.push(DawnStateChange::Investigate {
+                        actor,
+                        target,
+                        result,
+                    });
+                    changes.push(DawnStateChange::Visit { actor, target });
+                }
+                ScriptEffect::Visit(target) => {
+                    changes.push(DawnStateChange::Visit { actor, target });
+                }
+                ScriptEffect::Message(player, text) => {
+                    events.send(Event::ScriptMessage { player, text }).await?;
                 }
-                events.send(Event::Investigate { actor, target }).await?;
-            }
-            Role::DOCTOR => {
-                events.send(Event::Save { actor, target }).await?;
-                return Ok(vec![DawnStateChange::Save { actor, target }]);
-            }
-            Role::STRIPPER => {
-                events.send(Event::Block { actor, target }).await?;
-                return Ok(vec![DawnStateChange::Block { actor, target }]);
             }
+        }
+        Ok(changes)
+    }
+
+    // Learn who the target visited
+    async fn track(
+        &self,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events: &EventTx<PID>,
+    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
+        let actor = self.actor;
+        let target = self.target;
+        if let Some(outcome) = Self::check_stopped(actor, dawn_state, rules, events).await? {
+            return Ok(vec![DawnStateChange::outcome(actor, outcome)]);
+        }
+        let visited = dawn_state.visited_by(target);
+        events
+            .send(Event::Track {
+                actor,
+                target,
+                visited,
+            })
+            .await?;
+        Ok(vec![DawnStateChange::Visit { actor, target }])
+    }
 
-            _ => {}
+    // Learn who visited the target
+    async fn watch(
+        &self,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events: &EventTx<PID>,
+    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
+        let actor = self.actor;
+        let target = self.target;
+        if let Some(outcome) = Self::check_stopped(actor, dawn_state, rules, events).await? {
+            return Ok(vec![DawnStateChange::outcome(actor, outcome)]);
         }
-        Ok(vec![])
+        let mut visitors = dawn_state.visitors_of(target);
+        visitors.retain(|&visitor| visitor != actor);
+        events
+            .send(Event::Watch {
+                actor,
+                target,
+                visitors,
+            })
+            .await?;
+        Ok(vec![DawnStateChange::Visit { actor, target }])
+    }
+
+    // Take the target's vote for the next day
+    async fn steal(
+        &self,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events: &EventTx<PID>,
+    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
+        let actor = self.actor;
+        let target = self.target;
+        if let Some(outcome) = Self::check_stopped(actor, dawn_state, rules, events).await? {
+            return Ok(vec![DawnStateChange::outcome(actor, outcome)]);
+        }
+        events.send(Event::Steal { actor, target }).await?;
+        Ok(vec![
+            DawnStateChange::Steal { actor, target },
+            DawnStateChange::Visit { actor, target },
+        ])
+    }
+
+    // Whether the actor was killed or blocked before acting. Blocks are made evident to the actor.
+    async fn check_stopped(
+        actor: PID,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events: &EventTx<PID>,
+    ) -> Result<Option<NightOutcome>, CoreError<PID>> {
+        if dawn_state.killed.contains_key(&actor) {
+            return Ok(Some(NightOutcome::Killed));
+        }
+        if let Some(blockers) = dawn_state.blockers_info(actor, rules) {
+            events
+                .send(Event::EvidentBlock {
+                    blocked: actor,
+                    blockers,
+                })
+                .await?;
+            return Ok(Some(NightOutcome::Blocked));
+        }
+        Ok(None)
     }
 
+    // One faction's kill
     pub async fn perform_scheme(
-        scheme: &Option<(PID, Choice<PID>)>,
+        scheme: &(PID, Choice<PID>),
         dawn_state: &DawnState<PID>,
+        rules: &Rules,
         events_tx: &EventTx<PID>,
     ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
-        if let &Some((killer, Choice::Player(mark))) = scheme {
-            // TODO: if killer was blocked or killed, do nothing
-            let mut saved = false;
-            // Check for saviors
-            if let Some(saviors) = dawn_state.saves.get(&mark) {
-                for &savior in saviors {
-                    // Check if doctor was killed or blocked?
-                    if dawn_state.killed.contains_key(&savior) {
-                        continue;
+        if let &(chosen, Choice::Player(mark)) = scheme {
+            let mut changes = Vec::new();
+            // The chosen killer can be stopped by a block, or by being killed first
+            let stopped = if dawn_state.killed.contains_key(&chosen) {
+                Some(NightOutcome::Killed)
+            } else if let Some(blockers) = dawn_state.blockers_info(chosen, rules) {
+                events_tx
+                    .send(Event::EvidentBlock {
+                        blocked: chosen,
+                        blockers,
+                    })
+                    .await?;
+                Some(NightOutcome::Blocked)
+            } else {
+                None
+            };
+            let killer = match stopped {
+                None => Some(chosen),
+                Some(outcome) => {
+                    changes.push(DawnStateChange::outcome(chosen, outcome));
+                    match rules.night_rules.stopped_killer {
+                        StoppedKiller::Fail => None,
+                        StoppedKiller::Pass => dawn_state.backup_killer(chosen),
                     }
-                    if let Some(blockers) = dawn_state.blocks.get(&savior) {
-                        events_tx
-                            .send(Event::EvidentBlock {
-                                blocked: savior,
-                                blockers: blockers.clone(),
-                            })
-                            .await?;
-                        continue;
-                    }
-                    saved = true;
-                    events_tx.send(Event::EvidentSave { savior, mark }).await?;
                 }
+            };
+            if let (Some(_), Some(killer)) = (stopped, killer) {
+                changes.push(DawnStateChange::Pass {
+                    stopped: chosen,
+                    killer,
+                });
             }
-            if !saved {
+            let Some(killer) = killer else {
+                return Ok(changes);
+            };
+
+            let (save_changes, saved) =
+                Self::check_saves(mark, dawn_state, rules, events_tx).await?;
+            changes.extend(save_changes);
+            changes.push(DawnStateChange::Visit {
+                actor: killer,
+                target: mark,
+            });
+            if saved {
+                return Ok(changes);
+            }
+            if Self::check_bulletproof(mark, dawn_state, events_tx).await? {
+                changes.push(DawnStateChange::Absorb { mark });
+                changes.push(DawnStateChange::outcome(killer, NightOutcome::Survived));
+            } else {
                 events_tx.send(Event::Kill { killer, mark }).await?;
-                return Ok(vec![DawnStateChange::Kill { killer, mark }]);
+                changes.push(DawnStateChange::Kill { killer, mark });
             }
+            return Ok(changes);
         }
         Ok(vec![])
     }
+
+    // Whether the mark of a kill survives it by being bulletproof. Made evident to everyone.
+    async fn check_bulletproof(
+        mark: PID,
+        dawn_state: &DawnState<PID>,
+        events_tx: &EventTx<PID>,
+    ) -> Result<bool, CoreError<PID>> {
+        if !dawn_state.has_modifier(mark, Modifier::Bulletproof)
+            || dawn_state.absorbed.contains(&mark)
+        {
+            return Ok(false);
+        }
+        events_tx.send(Event::EvidentBulletproof { mark }).await?;
+        Ok(true)
+    }
+
+    // Checks the saves made on the mark of a kill. Returns the changes and whether the mark was saved.
+    async fn check_saves(
+        mark: PID,
+        dawn_state: &DawnState<PID>,
+        rules: &Rules,
+        events_tx: &EventTx<PID>,
+    ) -> Result<(Vec<DawnStateChange<PID>>, bool), CoreError<PID>> {
+        let mut changes = Vec::new();
+        let mut saved = false;
+        let Some(saviors) = dawn_state.saves.get(&mark) else {
+            return Ok((changes, saved));
+        };
+        for &savior in saviors {
+            // Check if doctor was killed or blocked?
+            if dawn_state.killed.contains_key(&savior) {
+                changes.push(DawnStateChange::outcome(savior, NightOutcome::Killed));
+                continue;
+            }
+            if let Some(blockers) = dawn_state.blockers_info(savior, rules) {
+                events_tx
+                    .send(Event::EvidentBlock {
+                        blocked: savior,
+                        blockers,
+                    })
+                    .await?;
+                continue;
+            }
+            saved = true;
+            changes.push(DawnStateChange::Rescue { savior, mark });
+            let savior = RoleInfo::new(dawn_state.players[&savior], rules.info_rules.save);
+            match rules.doctor_rules.save_disclosure {
+                SaveDisclosure::Public => {
+                    events_tx.send(Event::EvidentSave { savior, mark }).await?;
+                }
+                SaveDisclosure::Saved => {
+                    events_tx.send(Event::PrivateSave { savior, mark }).await?;
+                }
+                SaveDisclosure::Nobody => {}
+            }
+        }
+        Ok((changes, saved))
+    }
+}
+
+// The result of a night investigation, kept for post-game summaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Investigation<PID> {
+    pub night: u32,
+    pub actor: PID,
+    pub target: PID,
+    pub result: RoleInfo<PID>,
 }
 
 // mutated by Roles at dawn
 #[derive(Debug)]
 pub struct DawnState<PID: ID> {
+    pub night: u32,
+    pub players: HashMap<PID, Role<PID>>, // living players at the start of dawn
+    pub modifiers: HashMap<PID, Vec<Modifier>>,
     pub blocks: HashMap<PID, Vec<PID>>,
     pub saves: HashMap<PID, Vec<PID>>,
     pub killed: HashMap<PID, Vec<PID>>, // marks -> killer
+    pub rescued: Vec<(PID, PID)>,       // savior, mark
+    pub absorbed: Vec<PID>,             // bulletproof marks who survived a kill
+    pub acting: Vec<PID>,               // players who targeted a player with their own action
+    pub visits: Vec<(PID, PID)>,        // visitor, target of every successful night visit
+    pub stolen: HashMap<PID, PID>,      // victim -> thief, for the next day's votes
+    pub investigations: Vec<Investigation<PID>>,
+    pub outcomes: HashMap<PID, NightOutcome>, // actors whose action didn't simply go ahead
+    pub passed: HashMap<PID, PID>, // stopped killer -> teammate who killed in their place
 }
 
 pub enum DawnStateChange<PID: ID> {
-    Block { actor: PID, target: PID },
-    Save { actor: PID, target: PID },
-    Kill { killer: PID, mark: PID },
+    Block {
+        actor: PID,
+        target: PID,
+    },
+    Save {
+        actor: PID,
+        target: PID,
+    },
+    Kill {
+        killer: PID,
+        mark: PID,
+    },
+    Investigate {
+        actor: PID,
+        target: PID,
+        result: RoleInfo<PID>,
+    },
+    Rescue {
+        savior: PID,
+        mark: PID,
+    },
+    Absorb {
+        mark: PID,
+    },
+    Visit {
+        actor: PID,
+        target: PID,
+    },
+    Steal {
+        actor: PID,
+        target: PID,
+    },
+    Outcome {
+        actor: PID,
+        outcome: NightOutcome,
+    },
+    Pass {
+        stopped: PID,
+        killer: PID,
+    },
+}
+
+impl<PID: ID> DawnStateChange<PID> {
+    fn outcome(actor: PID, outcome: NightOutcome) -> Self {
+        DawnStateChange::Outcome { actor, outcome }
+    }
 }
 
 impl<PID: ID> DawnState<PID> {
+    pub fn new(
+        night: u32,
+        players: HashMap<PID, Role<PID>>,
+        modifiers: HashMap<PID, Vec<Modifier>>,
+    ) -> Self {
+        DawnState {
+            night,
+            players,
+            modifiers,
+            blocks: HashMap::new(),
+            saves: HashMap::new(),
+            killed: HashMap::new(),
+            rescued: Vec::new(),
+            absorbed: Vec::new(),
+            acting: Vec::new(),
+            visits: Vec::new(),
+            stolen: HashMap::new(),
+            investigations: Vec::new(),
+            outcomes: HashMap::new(),
+            passed: HashMap::new(),
+        }
+    }
+
+    pub fn has_modifier(&self, player: PID, modifier: Modifier) -> bool {
+        self.modifiers
+            .get(&player)
+            .is_some_and(|modifiers| modifiers.contains(&modifier))
+    }
+
+    // What a blocked player learns about their blockers, or None if they weren't blocked
+    pub fn blockers_info(&self, blocked: PID, rules: &Rules) -> Option<Vec<RoleInfo<PID>>> {
+        let blockers = self.blocks.get(&blocked)?;
+        let level = rules.info_rules.block;
+        let info = blockers
+            .iter()
+            .filter_map(|blocker| self.players.get(blocker))
+            .map(|&role| RoleInfo::new(role, level))
+            .collect();
+        Some(info)
+    }
+
+    // The first living member of the stopped killer's faction who can still kill, and hasn't
+    //   used their own action tonight
+    pub fn backup_killer(&self, stopped: PID) -> Option<PID> {
+        let team = self.players[&stopped].team();
+        let mut mafia: Vec<PID> = self
+            .players
+            .iter()
+            .filter(|(_, role)| role.team() == team)
+            .map(|(&player, _)| player)
+            .filter(|player| {
+                *player != stopped
+                    && !self.killed.contains_key(player)
+                    && !self.blocks.contains_key(player)
+                    && !self.acting.contains(player)
+            })
+            .collect();
+        mafia.sort();
+        mafia.first().copied()
+    }
+
+    // Players the visitor visited so far tonight
+    pub fn visited_by(&self, visitor: PID) -> Vec<PID> {
+        let mut targets: Vec<PID> = self
+            .visits
+            .iter()
+            .filter(|&&(v, _)| v == visitor)
+            .map(|&(_, target)| target)
+            .collect();
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+
+    // Players who visited the target so far tonight
+    pub fn visitors_of(&self, target: PID) -> Vec<PID> {
+        let mut visitors: Vec<PID> = self
+            .visits
+            .iter()
+            .filter(|&&(_, t)| t == target)
+            .map(|&(visitor, _)| visitor)
+            .collect();
+        visitors.sort();
+        visitors.dedup();
+        visitors
+    }
+
+    // Bulletproof only stops one kill, so absorbs apply right away instead of with their batch
+    pub fn apply_absorbs(&mut self, changes: &mut Vec<DawnStateChange<PID>>) {
+        changes.retain(|change| match *change {
+            DawnStateChange::Absorb { mark } => {
+                self.absorbed.push(mark);
+                false
+            }
+            _ => true,
+        });
+    }
+
     pub fn apply_changes(&mut self, changes: Vec<DawnStateChange<PID>>) {
         for change in changes {
             match change {
@@ -198,6 +974,36 @@ impl<PID: ID> DawnState<PID> {
                 DawnStateChange::Kill { killer, mark } => {
                     self.killed.entry(mark).or_insert(Vec::new()).push(killer);
                 }
+                DawnStateChange::Investigate {
+                    actor,
+                    target,
+                    result,
+                } => {
+                    self.investigations.push(Investigation {
+                        night: self.night,
+                        actor,
+                        target,
+                        result,
+                    });
+                }
+                DawnStateChange::Rescue { savior, mark } => {
+                    self.rescued.push((savior, mark));
+                }
+                DawnStateChange::Absorb { mark } => {
+                    self.absorbed.push(mark);
+                }
+                DawnStateChange::Visit { actor, target } => {
+                    self.visits.push((actor, target));
+                }
+                DawnStateChange::Steal { actor, target } => {
+                    self.stolen.insert(target, actor);
+                }
+                DawnStateChange::Outcome { actor, outcome } => {
+                    self.outcomes.insert(actor, outcome);
+                }
+                DawnStateChange::Pass { stopped, killer } => {
+                    self.passed.insert(stopped, killer);
+                }
             }
         }
     }
@@ -205,53 +1011,16 @@ impl<PID: ID> DawnState<PID> {
 
 impl<PID: ID> Role<PID> {
     // Larger priority happens first at dawn. Equal priorities can happen in any order.
-    // Positive happens before mafia scheme is resolved. Negative happens after mafia scheme is resolved.
-    pub fn night_action_priority(&self) -> Option<i8> {
+    // Positive happens before the schemes are resolved. Zero and negative happen after the schemes are resolved.
+    pub fn night_action_priority(&self, rules: &Rules) -> Option<i8> {
         return match self {
-            Role::COP => Some(-1),
+            Role::COP(_) | Role::THIEF => Some(-1),
+            Role::TRACKER | Role::WATCHER => Some(-2),
             Role::DOCTOR => Some(1),
             Role::STRIPPER => Some(2),
+            Role::VIGILANTE => Some(rules.night_rules.vigilante_priority),
+            Role::CUSTOM(custom) if custom.action != ActionType::None => Some(custom.priority),
             _ => None,
         };
     }
-
-    pub async fn night_action(
-        &self,
-        actor: PID,
-        target: PID,
-        dawn_state: &DawnState<PID>,
-        events: &EventTx<PID>,
-    ) -> Result<Vec<DawnStateChange<PID>>, CoreError<PID>> {
-        match self {
-            Role::COP => {
-                // if cop was killed, do nothing
-                if dawn_state.killed.contains_key(&actor) {
-                    return Ok(vec![]);
-                }
-                // if target was killed, do nothing
-                if dawn_state.killed.contains_key(&target) {
-                    return Ok(vec![]);
-                }
-                if let Some((&blocked, blockers)) = dawn_state.blocks.get_key_value(&actor) {
-                    let blockers = blockers.clone();
-                    events
-                        .send(Event::EvidentBlock { blocked, blockers })
-                        .await?;
-                    return Ok(vec![]);
-                }
-                events.send(Event::Investigate { actor, target }).await?;
-            }
-            Role::DOCTOR => {
-                events.send(Event::Save { actor, target }).await?;
-                return Ok(vec![DawnStateChange::Save { actor, target }]);
-            }
-            Role::STRIPPER => {
-                events.send(Event::Block { actor, target }).await?;
-                return Ok(vec![DawnStateChange::Block { actor, target }]);
-            }
-
-            _ => {}
-        }
-        Ok(vec![])
-    }
 }
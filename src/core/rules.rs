@@ -1,3 +1,6 @@
+use crate::roledefs::RoleDefinition;
+use crate::roles::{RoleKind, Sanity};
+
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DurationSecondsWithFrac};
 use std::fmt::Debug;
@@ -11,6 +14,8 @@ What rules are there?
     - dawn timer after all roles have acted
     - General Phase timer rules
     - Refocus rules for GUARD and AGENT
+    - What happens to the night kill when the chosen killer is blocked or killed
+    - Who the DOCTOR may save, and who learns about a save
 2. Information rules, or how information is distributed
     - start_roles: What Roles/Teams are known at the start of the game?
         - roles: number of each role kind is known
@@ -24,12 +29,24 @@ What rules are there?
 3. Rolegen
     - What roles are available to the rolegen engine?
     - Other Input parameters for the rolegen engine
+4. Role definitions, for custom roles defined in data
 
 */
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Rules {
     pub timer_rules: TimerRules,
+    #[serde(default)]
+    pub info_rules: InfoRules,
+    #[serde(default)]
+    pub rolegen_rules: RolegenRules,
+    #[serde(default)]
+    pub night_rules: NightRules,
+    #[serde(default)]
+    pub doctor_rules: DoctorRules,
+    // Custom roles are found here by their id
+    #[serde(default)]
+    pub role_definitions: Vec<RoleDefinition>,
 }
 
 #[serde_as]
@@ -41,6 +58,17 @@ pub struct TimerRules {
     #[serde(default)]
     #[serde_as(as = "DurationSecondsWithFrac<f64>")]
     pub dawn_imminent_time: Duration,
+    // Maximum length of a day. No limit if unset
+    #[serde(default)]
+    #[serde_as(as = "Option<DurationSecondsWithFrac<f64>>")]
+    pub day_time: Option<Duration>,
+    // Maximum length of a night. No limit if unset
+    #[serde(default)]
+    #[serde_as(as = "Option<DurationSecondsWithFrac<f64>>")]
+    pub night_time: Option<Duration>,
+    // How the day is resolved when day_time runs out
+    #[serde(default)]
+    pub day_deadline: DayDeadline,
 }
 
 impl Default for TimerRules {
@@ -48,6 +76,265 @@ impl Default for TimerRules {
         Self {
             election_imminent_time: Duration::from_secs_f64(10.0),
             dawn_imminent_time: Duration::from_secs_f64(10.0),
+            day_time: None,
+            night_time: None,
+            day_deadline: DayDeadline::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DayDeadline {
+    // Nobody is elected
+    #[default]
+    NoElimination,
+    // The choice with the most votes is elected. Nobody is elected on a tie
+    Plurality,
+}
+
+// How night actions are resolved at dawn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NightRules {
+    pub stopped_killer: StoppedKiller,
+    // When the VIGILANTE shoots at dawn. Below DOCTOR and STRIPPER, so saves and blocks apply
+    pub vigilante_priority: i8,
+    // Charges, or shots, a VIGILANTE starts with
+    pub vigilante_shots: u8,
+    pub scheme_decision: SchemeDecision,
+    pub scheme_tie_break: SchemeTieBreak,
+}
+
+impl Default for NightRules {
+    fn default() -> Self {
+        Self {
+            stopped_killer: StoppedKiller::default(),
+            vigilante_priority: 0,
+            vigilante_shots: 1,
+            scheme_decision: SchemeDecision::default(),
+            scheme_tie_break: SchemeTieBreak::default(),
+        }
+    }
+}
+
+// How a faction settles on its kill from its members' picks
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemeDecision {
+    // The latest pick is the kill
+    #[default]
+    Last,
+    // Only the faction's designated killer decides. Until a member designates one, or once
+    //   they die, it's the first living member in player order
+    Designated,
+    // More than half the living members have to pick the same mark
+    Majority,
+}
+
+// How a majority decision is settled when marks tie for the most picks, once every
+//   member has picked or the night ends
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchemeTieBreak {
+    // Nobody is killed
+    #[default]
+    NoKill,
+    // Of the marks with the most picks, the one picked first
+    FirstPick,
+}
+
+// What happens to a faction's kill when its chosen killer is blocked or killed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoppedKiller {
+    // Nobody is killed
+    #[default]
+    Fail,
+    // Another living member of the faction who wasn't stopped does the kill
+    Pass,
+}
+
+// Who the DOCTOR may save, and who learns about a save
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DoctorRules {
+    pub allow_self_save: bool,
+    // Whether the same player can be saved on two nights in a row
+    pub allow_consecutive_saves: bool,
+    pub save_disclosure: SaveDisclosure,
+}
+
+impl Default for DoctorRules {
+    fn default() -> Self {
+        Self {
+            allow_self_save: true,
+            allow_consecutive_saves: true,
+            save_disclosure: SaveDisclosure::default(),
+        }
+    }
+}
+
+// Who learns that a kill was stopped by a save
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaveDisclosure {
+    // Everyone
+    #[default]
+    Public,
+    // Only the saved player
+    Saved,
+    // Nobody, the kill just doesn't happen
+    Nobody,
+}
+
+// How information is distributed to players
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InfoRules {
+    pub start_roles: StartRoles,
+    // What is revealed about the eliminated player upon an election
+    pub election: InfoLevel,
+    // What is revealed about the eliminated player upon a kill
+    pub kill: InfoLevel,
+    // What the blocked player learns about their blockers
+    pub block: InfoLevel,
+    // What is revealed about the savior upon a save
+    pub save: InfoLevel,
+    // What an investigator learns about their target
+    pub investigation: InfoLevel,
+}
+
+// What is publicly known about the setup at the start of the game
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartRoles {
+    // Number of each role kind is known
+    #[default]
+    Roles,
+    // Number of each team is known
+    Teams,
+    // Number of mafia and non-mafia is known
+    Mafia,
+}
+
+// How much is revealed about a player's role
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InfoLevel {
+    #[default]
+    Role,
+    Team,
+    Mafia,
+    Nothing,
+}
+
+// Input parameters for the rolegen engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RolegenRules {
+    // Fraction of players on the mafia team (at least one mafia is always generated)
+    pub mafia_ratio: f64,
+    // Fraction of players on the rogue team (rounded down)
+    pub rogue_ratio: f64,
+    // Roles available to the rolegen engine
+    pub roles: Vec<RoleOption>,
+    // Sanities handed out to each COP. Cops are sane if this is empty
+    pub cop_sanities: Vec<SanityOption>,
+}
+
+// A role in the rolegen pool, with how often it should show up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleOption {
+    pub role: RoleKind,
+    // Relative chance of picking this role when filling a team
+    #[serde(default = "RoleOption::default_weight")]
+    pub weight: u32,
+    #[serde(default)]
+    pub min: usize,
+    #[serde(default)]
+    pub max: Option<usize>,
+    // Players placed together each time this role is picked. min and max count these groups
+    #[serde(default = "RoleOption::default_group")]
+    pub group: usize,
+    // Name of the role definition, for CUSTOM roles
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl RoleOption {
+    pub fn new(role: RoleKind, weight: u32, min: usize, max: Option<usize>) -> Self {
+        Self {
+            role,
+            weight,
+            min,
+            max,
+            group: 1,
+            name: None,
+        }
+    }
+
+    pub fn custom(name: &str, weight: u32, min: usize, max: Option<usize>) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            ..Self::new(RoleKind::CUSTOM, weight, min, max)
+        }
+    }
+
+    pub fn with_group(mut self, group: usize) -> Self {
+        self.group = group;
+        self
+    }
+
+    fn default_weight() -> u32 {
+        1
+    }
+
+    fn default_group() -> usize {
+        1
+    }
+}
+
+// A COP sanity, with how often it should show up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SanityOption {
+    pub sanity: Sanity,
+    #[serde(default = "RoleOption::default_weight")]
+    pub weight: u32,
+}
+
+impl SanityOption {
+    pub fn new(sanity: Sanity, weight: u32) -> Self {
+        Self { sanity, weight }
+    }
+}
+
+impl Default for RolegenRules {
+    fn default() -> Self {
+        Self {
+            mafia_ratio: 0.25,
+            rogue_ratio: 0.0,
+            roles: vec![
+                RoleOption::new(RoleKind::TOWN, 4, 0, None),
+                RoleOption::new(RoleKind::COP, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::DOCTOR, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::CELEB, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::VIGILANTE, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::MILLER, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::TRACKER, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::WATCHER, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::MASON, 1, 0, Some(1)).with_group(2),
+                RoleOption::new(RoleKind::MAYOR, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::MAFIA, 2, 0, None),
+                RoleOption::new(RoleKind::STRIPPER, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::GODFATHER, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::THIEF, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::IDIOT, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::SURVIVOR, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::GUARD, 1, 0, Some(1)),
+                RoleOption::new(RoleKind::AGENT, 1, 0, Some(1)),
+            ],
+            cop_sanities: vec![SanityOption::new(Sanity::Sane, 1)],
         }
     }
 }
@@ -57,7 +344,14 @@ impl Rules {
         let mut timer_rules = TimerRules::default();
         timer_rules.election_imminent_time = Duration::from_secs_f64(0.2);
         timer_rules.dawn_imminent_time = Duration::from_secs_f64(0.2);
-        Self { timer_rules }
+        Self {
+            timer_rules,
+            info_rules: InfoRules::default(),
+            rolegen_rules: RolegenRules::default(),
+            night_rules: NightRules::default(),
+            doctor_rules: DoctorRules::default(),
+            role_definitions: Vec::new(),
+        }
     }
 }
 
@@ -78,9 +372,75 @@ mod test {
         [timer_rules]
         election_imminent_time = 10.0
         dawn_imminent_time = 10.0
+        day_time = 600.0
+        day_deadline = "plurality"
+
+        [info_rules]
+        start_roles = "teams"
+        kill = "team"
+        save = "nothing"
+
+        [night_rules]
+        stopped_killer = "pass"
+        vigilante_priority = -2
+        vigilante_shots = 2
+        scheme_decision = "majority"
+
+        [doctor_rules]
+        allow_self_save = false
+        save_disclosure = "saved"
+
+        [rolegen_rules]
+        mafia_ratio = 0.3
+        rogue_ratio = 0.1
+
+        [[rolegen_rules.roles]]
+        role = "TOWN"
+        weight = 3
+
+        [[rolegen_rules.roles]]
+        role = "COP"
+        min = 1
+        max = 1
+
+        [[rolegen_rules.roles]]
+        role = "MAFIA"
+
+        [[rolegen_rules.cop_sanities]]
+        sanity = "sane"
+        weight = 3
+
+        [[rolegen_rules.cop_sanities]]
+        sanity = "insane"
+
+        [[role_definitions]]
+        name = "SEER"
+        team = "Town"
+        action = "investigate"
         "#;
 
         let rules: Rules = toml::from_str(toml_str).unwrap();
         println!("{:?}", rules);
+        assert_eq!(rules.timer_rules.day_time, Some(Duration::from_secs(600)));
+        assert_eq!(rules.timer_rules.night_time, None);
+        assert_eq!(rules.timer_rules.day_deadline, DayDeadline::Plurality);
+        assert_eq!(rules.info_rules.start_roles, StartRoles::Teams);
+        assert_eq!(rules.info_rules.election, InfoLevel::Role);
+        assert_eq!(rules.info_rules.kill, InfoLevel::Team);
+        assert_eq!(rules.info_rules.save, InfoLevel::Nothing);
+        assert_eq!(rules.night_rules.stopped_killer, StoppedKiller::Pass);
+        assert_eq!(rules.night_rules.vigilante_priority, -2);
+        assert_eq!(rules.night_rules.vigilante_shots, 2);
+        assert_eq!(rules.night_rules.scheme_decision, SchemeDecision::Majority);
+        assert_eq!(rules.night_rules.scheme_tie_break, SchemeTieBreak::NoKill);
+        assert!(!rules.doctor_rules.allow_self_save);
+        assert!(rules.doctor_rules.allow_consecutive_saves);
+        assert_eq!(rules.doctor_rules.save_disclosure, SaveDisclosure::Saved);
+        assert_eq!(rules.rolegen_rules.roles.len(), 3);
+        assert_eq!(rules.rolegen_rules.roles[1].max, Some(1));
+        assert_eq!(rules.rolegen_rules.roles[2].weight, 1);
+        assert_eq!(rules.rolegen_rules.cop_sanities[1].sanity, Sanity::Insane);
+        assert_eq!(rules.rolegen_rules.cop_sanities[1].weight, 1);
+        assert_eq!(rules.role_definitions[0].name, "SEER");
     }
 }
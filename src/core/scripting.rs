@@ -0,0 +1,335 @@
+// Night actions written as rhai scripts, for custom roles with the script action.
+// A script sees a read only view of the dawn so far, as `dawn`, along with `actor` and `target`.
+//   Players are numbered by their place in `dawn.players`. The script returns an array of
+//   effects, built with block(p), save(p), kill(p), investigate(p), visit(p) and message(p, text).
+// Scripts run in a fresh engine with limits on steps, depth and sizes, so a bad script fails
+//   instead of hanging the game.
+
+use crate::base::ID;
+use crate::roledefs;
+use crate::roles::{DawnState, Modifier, Role};
+use crate::rules::Rules;
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, ImmutableString, Map, Scope, AST};
+
+// Limits for every script run
+pub const MAX_OPERATIONS: u64 = 50_000;
+const MAX_CALL_LEVELS: usize = 16;
+const MAX_EXPR_DEPTH: usize = 32;
+const MAX_STRING_SIZE: usize = 1_000;
+const MAX_COLLECTION_SIZE: usize = 1_000;
+
+// What a script asked for, with players resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptEffect<PID> {
+    Block(PID),
+    Save(PID),
+    Kill(PID),
+    Investigate(PID),
+    Visit(PID),
+    Message(PID, String),
+}
+
+// Read only view of the dawn given to scripts. Players are indices into the sorted living players.
+#[derive(Debug, Clone)]
+struct DawnView {
+    night: i64,
+    roles: Vec<ImmutableString>,
+    teams: Vec<ImmutableString>,
+    modifiers: Vec<Vec<ImmutableString>>,
+    blocked: Vec<bool>,
+    saved: Vec<bool>,
+    killed: Vec<bool>,
+    visits: Vec<(i64, i64)>,
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+impl DawnView {
+    fn new<PID: ID>(players: &[PID], dawn_state: &DawnState<PID>, rules: &Rules) -> Self {
+        let index = |player: &PID| players.binary_search(player).ok().map(|i| i as i64);
+        let roles = players.iter().map(|p| dawn_state.players[p]);
+        DawnView {
+            night: dawn_state.night as i64,
+            roles: roles.clone().map(|role| role_name(role, rules)).collect(),
+            teams: roles
+                .map(|role| format!("{:?}", role.team()).into())
+                .collect(),
+            modifiers: players
+                .iter()
+                .map(|p| {
+                    dawn_state
+                        .modifiers
+                        .get(p)
+                        .into_iter()
+                        .flatten()
+                        .map(|&modifier| modifier_name(modifier).into())
+                        .collect()
+                })
+                .collect(),
+            blocked: players
+                .iter()
+                .map(|p| dawn_state.blocks.contains_key(p))
+                .collect(),
+            saved: players
+                .iter()
+                .map(|p| dawn_state.saves.contains_key(p))
+                .collect(),
+            killed: players
+                .iter()
+                .map(|p| dawn_state.killed.contains_key(p))
+                .collect(),
+            visits: dawn_state
+                .visits
+                .iter()
+                .filter_map(|(visitor, target)| Some((index(visitor)?, index(target)?)))
+                .collect(),
+        }
+    }
+
+    fn index(&self, player: i64) -> ScriptResult<usize> {
+        match usize::try_from(player) {
+            Ok(i) if i < self.roles.len() => Ok(i),
+            _ => Err(format!("No player {}", player).into()),
+        }
+    }
+
+    fn players(&mut self) -> Array {
+        (0..self.roles.len() as i64).map(Dynamic::from).collect()
+    }
+
+    fn night(&mut self) -> i64 {
+        self.night
+    }
+
+    fn role(&mut self, player: i64) -> ScriptResult<ImmutableString> {
+        Ok(self.roles[self.index(player)?].clone())
+    }
+
+    fn team(&mut self, player: i64) -> ScriptResult<ImmutableString> {
+        Ok(self.teams[self.index(player)?].clone())
+    }
+
+    fn has_modifier(&mut self, player: i64, modifier: &str) -> ScriptResult<bool> {
+        Ok(self.modifiers[self.index(player)?]
+            .iter()
+            .any(|m| m == modifier))
+    }
+
+    fn is_blocked(&mut self, player: i64) -> ScriptResult<bool> {
+        Ok(self.blocked[self.index(player)?])
+    }
+
+    fn is_saved(&mut self, player: i64) -> ScriptResult<bool> {
+        Ok(self.saved[self.index(player)?])
+    }
+
+    fn is_killed(&mut self, player: i64) -> ScriptResult<bool> {
+        Ok(self.killed[self.index(player)?])
+    }
+
+    fn visited_by(&mut self, visitor: i64) -> ScriptResult<Array> {
+        self.index(visitor)?;
+        let mut targets: Vec<i64> = self
+            .visits
+            .iter()
+            .filter(|&&(v, _)| v == visitor)
+            .map(|&(_, target)| target)
+            .collect();
+        targets.sort();
+        targets.dedup();
+        Ok(targets.into_iter().map(Dynamic::from).collect())
+    }
+
+    fn visitors_of(&mut self, target: i64) -> ScriptResult<Array> {
+        self.index(target)?;
+        let mut visitors: Vec<i64> = self
+            .visits
+            .iter()
+            .filter(|&&(_, t)| t == target)
+            .map(|&(visitor, _)| visitor)
+            .collect();
+        visitors.sort();
+        visitors.dedup();
+        Ok(visitors.into_iter().map(Dynamic::from).collect())
+    }
+}
+
+fn role_name<PID: ID>(role: Role<PID>, rules: &Rules) -> ImmutableString {
+    match roledefs::definition(&rules.role_definitions, &role) {
+        Some(definition) => definition.name.as_str().into(),
+        None => format!("{:?}", role.kind()).into(),
+    }
+}
+
+fn modifier_name(modifier: Modifier) -> &'static str {
+    match modifier {
+        Modifier::Bulletproof => "bulletproof",
+        Modifier::BlockImmune => "block_immune",
+        Modifier::InvestigateImmune => "investigate_immune",
+        Modifier::OneShot(_) => "one_shot",
+    }
+}
+
+fn effect(kind: &str, player: i64) -> Map {
+    let mut map = Map::new();
+    map.insert("effect".into(), kind.into());
+    map.insert("player".into(), player.into());
+    map
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE)
+        .set_max_modules(0)
+        .on_print(|_| {})
+        .on_debug(|_, _, _| {});
+
+    engine
+        .register_type_with_name::<DawnView>("DawnView")
+        .register_get("players", DawnView::players)
+        .register_get("night", DawnView::night)
+        .register_fn("role", DawnView::role)
+        .register_fn("team", DawnView::team)
+        .register_fn("has_modifier", DawnView::has_modifier)
+        .register_fn("is_blocked", DawnView::is_blocked)
+        .register_fn("is_saved", DawnView::is_saved)
+        .register_fn("is_killed", DawnView::is_killed)
+        .register_fn("visited_by", DawnView::visited_by)
+        .register_fn("visitors_of", DawnView::visitors_of);
+
+    for kind in ["block", "save", "kill", "investigate", "visit"] {
+        engine.register_fn(kind, move |player: i64| effect(kind, player));
+    }
+    engine.register_fn("message", |player: i64, text: ImmutableString| {
+        let mut map = effect("message", player);
+        map.insert("text".into(), text.into());
+        map
+    });
+    engine
+}
+
+// Check a script parses, without running it
+pub fn compile(script: &str) -> Result<(), String> {
+    engine()
+        .compile(script)
+        .map(|_: AST| ())
+        .map_err(|e| e.to_string())
+}
+
+// Run the actor's script against the dawn so far. Errors are described for the moderators.
+// The engine is dropped before returning, so callers can hold the result across awaits.
+pub fn run<PID: ID>(
+    script: &str,
+    actor: PID,
+    target: PID,
+    dawn_state: &DawnState<PID>,
+    rules: &Rules,
+) -> Result<Vec<ScriptEffect<PID>>, String> {
+    let mut players: Vec<PID> = dawn_state.players.keys().copied().collect();
+    players.sort();
+    let index = |player: PID| {
+        players
+            .binary_search(&player)
+            .map(|i| i as i64)
+            .map_err(|_| format!("{} is not a living player", player))
+    };
+
+    let mut scope = Scope::new();
+    scope.push_constant("dawn", DawnView::new(&players, dawn_state, rules));
+    scope.push_constant("actor", index(actor)?);
+    scope.push_constant("target", index(target)?);
+
+    let result: Dynamic = engine()
+        .eval_with_scope(&mut scope, script)
+        .map_err(|e| e.to_string())?;
+    if result.is_unit() {
+        return Ok(vec![]);
+    }
+    let effects: Array = result
+        .try_cast()
+        .ok_or_else(|| "Script must return an array of effects".to_string())?;
+
+    effects
+        .into_iter()
+        .map(|effect| {
+            let map: Map = effect
+                .try_cast()
+                .ok_or_else(|| "Effects must be built with block(p), save(p), etc".to_string())?;
+            let player = map
+                .get("player")
+                .and_then(|p| p.as_int().ok())
+                .and_then(|p| usize::try_from(p).ok())
+                .and_then(|p| players.get(p).copied())
+                .ok_or_else(|| "Effect is missing a valid player".to_string())?;
+            let kind = map
+                .get("effect")
+                .and_then(|k| k.clone().into_immutable_string().ok())
+                .unwrap_or_default();
+            match kind.as_str() {
+                "block" => Ok(ScriptEffect::Block(player)),
+                "save" => Ok(ScriptEffect::Save(player)),
+                "kill" => Ok(ScriptEffect::Kill(player)),
+                "investigate" => Ok(ScriptEffect::Investigate(player)),
+                "visit" => Ok(ScriptEffect::Visit(player)),
+                "message" => {
+                    let text = map.get("text").map(|t| t.to_string()).unwrap_or_default();
+                    Ok(ScriptEffect::Message(player, text))
+                }
+                other => Err(format!("Unknown effect {:?}", other)),
+            }
+        })
+        .collect()
+}
+
+mod test {
+
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_scripting() {
+        let players: HashMap<u32, Role<u32>> = [
+            (1, Role::TOWN),
+            (2, Role::MAFIA),
+            (3, Role::COP(Default::default())),
+        ]
+        .into();
+        let mut dawn_state = DawnState::new(1, players, HashMap::new());
+        dawn_state.visits.push((3, 2));
+        let rules = Rules::default();
+
+        let script = r#"
+            let effects = [];
+            if dawn.team(target) == "Mafia" {
+                effects.push(kill(target));
+            }
+            for visitor in dawn.visitors_of(target) {
+                effects.push(message(actor, "saw " + dawn.role(visitor)));
+            }
+            effects
+        "#;
+        assert_eq!(
+            run(script, 1, 2, &dawn_state, &rules),
+            Ok(vec![
+                ScriptEffect::Kill(2),
+                ScriptEffect::Message(1, "saw COP".to_string())
+            ])
+        );
+        assert_eq!(run(script, 1, 3, &dawn_state, &rules), Ok(vec![]));
+
+        // A script that never ends runs out of steps
+        assert!(run("loop {}", 1, 2, &dawn_state, &rules).is_err());
+        assert!(run("dawn.role(7)", 1, 2, &dawn_state, &rules).is_err());
+        assert!(run("[1]", 1, 2, &dawn_state, &rules).is_err());
+        assert!(compile("let x = ;").is_err());
+    }
+}
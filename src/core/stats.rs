@@ -0,0 +1,124 @@
+// Per-game record of what happened, for end of game breakdowns.
+
+use crate::base::{Choice, ID};
+use crate::roles::{Investigation, Modifier, Role, RoleKind};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats<PID: Eq + Hash> {
+    pub role_history: HashMap<PID, Vec<RoleRecord<PID>>>,
+    pub votes: Vec<VoteRecord<PID>>,
+    pub elections: Vec<ElectionRecord<PID>>,
+    pub night_actions: Vec<NightRecord<PID>>,
+    pub kills: Vec<KillRecord<PID>>,
+    pub saves: Vec<SaveRecord<PID>>,
+    pub blocks: Vec<BlockRecord<PID>>,
+    pub investigations: Vec<Investigation<PID>>,
+    pub days_survived: HashMap<PID, u32>, // filled in as players are eliminated, and at the end
+}
+
+// A role a player held, with the modifiers they had when they got it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoleRecord<PID> {
+    pub role: Role<PID>,
+    #[serde(default = "Vec::new")]
+    pub modifiers: Vec<Modifier>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteRecord<PID> {
+    pub day: u32,
+    pub voter: PID,
+    pub ballot: Option<Choice<PID>>, // None is an unvote
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ElectionRecord<PID> {
+    pub day: u32,
+    pub candidate: Choice<PID>,
+    pub hammer: PID,
+    pub voters: Vec<PID>,
+    // Weights of the voters whose vote didn't count as one
+    #[serde(default = "Vec::new")]
+    pub weights: Vec<(PID, i32)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NightRecord<PID> {
+    pub night: u32,
+    pub actor: PID,
+    pub role: RoleKind,
+    pub target: Choice<PID>,
+    pub scheme: bool, // A faction's kill, rather than the actor's own night action
+    pub outcome: NightOutcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NightOutcome {
+    Abstained,
+    Performed,
+    Blocked,
+    Killed,       // The actor was killed before they could act
+    TargetKilled, // The target was killed before the action happened
+    Saved,        // The mark of a kill was saved
+    Survived,     // The mark of a kill was bulletproof
+    Failed,       // The action's script failed
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KillRecord<PID> {
+    pub night: u32,
+    pub killer: PID,
+    pub mark: PID,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SaveRecord<PID> {
+    pub night: u32,
+    pub savior: PID,
+    pub mark: PID,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockRecord<PID> {
+    pub night: u32,
+    pub blocker: PID,
+    pub blocked: PID,
+}
+
+impl<PID: Eq + Hash> Stats<PID> {
+    pub fn new() -> Self {
+        Stats {
+            role_history: HashMap::new(),
+            votes: Vec::new(),
+            elections: Vec::new(),
+            night_actions: Vec::new(),
+            kills: Vec::new(),
+            saves: Vec::new(),
+            blocks: Vec::new(),
+            investigations: Vec::new(),
+            days_survived: HashMap::new(),
+        }
+    }
+}
+
+impl<PID: ID> Stats<PID> {
+    pub fn last_role(&self, player: PID) -> Option<Role<PID>> {
+        self.role_history
+            .get(&player)?
+            .last()
+            .map(|record| record.role)
+    }
+
+    // The actor's own night action target on the night before the given one
+    pub fn previous_target(&self, actor: PID, night: u32) -> Option<Choice<PID>> {
+        let previous = night.checked_sub(1)?;
+        self.night_actions
+            .iter()
+            .find(|record| record.night == previous && record.actor == actor && !record.scheme)
+            .map(|record| record.target)
+    }
+}
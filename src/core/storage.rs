@@ -0,0 +1,150 @@
+// Local storage for running games. Each game is kept as a single json file,
+//   rewritten after every action the core handles.
+
+use crate::base::ID;
+use crate::clock::ClockRef;
+use crate::core::Core;
+use crate::interface::{
+    CommandTx, DeserializeGameError, EventRx, SerializeGameError, SerializedGame,
+};
+
+use serde::de::DeserializeOwned;
+use serde_json;
+use std::fs;
+use std::io;
+use std::num::ParseIntError;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone)]
+pub struct GameStore {
+    pub dir: PathBuf,
+}
+
+// A game restored from storage, running with fresh channels
+pub struct RestoredGame<PID: ID, GID> {
+    pub game_id: GID,
+    pub join: JoinHandle<()>,
+    pub event_rx: EventRx<PID>,
+    pub cmd_tx: CommandTx<PID>,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Serialize(SerializeGameError),
+    Deserialize(DeserializeGameError),
+}
+
+impl GameStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, game_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", game_id))
+    }
+
+    pub fn save(&self, game: &SerializedGame) -> Result<(), StorageError> {
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first so a crash mid-write can't corrupt the snapshot
+        let path = self.path(&game.game_id);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string(game)?)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, game_id: &str) -> Result<(), StorageError> {
+        match fs::remove_file(self.path(game_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    // Every snapshot in the store. Files that can't be read or parsed are skipped.
+    pub fn load_all(&self) -> Result<Vec<SerializedGame>, StorageError> {
+        let mut games = Vec::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(games),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                match Self::load(&path) {
+                    Ok(game) => games.push(game),
+                    Err(e) => println!("Unable to load game {}: {:?}", path.display(), e),
+                }
+            }
+        }
+        Ok(games)
+    }
+
+    fn load(path: &Path) -> Result<SerializedGame, StorageError> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    // Spawn every unfinished game in storage on the given clock. Games that fail to load are skipped.
+    pub async fn restore_all<PID, GID>(
+        &self,
+        clock: ClockRef,
+    ) -> Result<Vec<RestoredGame<PID, GID>>, StorageError>
+    where
+        PID: ID + DeserializeOwned,
+        GID: ID + FromStr<Err = ParseIntError>,
+    {
+        let mut restored = Vec::new();
+        for saved in self.load_all()? {
+            let (core, event_rx, cmd_tx) = match Core::<PID, GID>::restore(&saved, clock.clone()) {
+                Ok(Some(restored)) => restored,
+                Ok(None) => {
+                    // Game was already over
+                    self.remove(&saved.game_id)?;
+                    continue;
+                }
+                Err(e) => {
+                    println!("Unable to restore game {}: {:?}", saved.game_id, e);
+                    continue;
+                }
+            };
+            let game_id = core.game_id;
+            let join = core.with_storage(self.clone()).spawn().await;
+            restored.push(RestoredGame {
+                game_id,
+                join,
+                event_rx,
+                cmd_tx,
+            });
+        }
+        Ok(restored)
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Json(e)
+    }
+}
+
+impl From<SerializeGameError> for StorageError {
+    fn from(e: SerializeGameError) -> Self {
+        StorageError::Serialize(e)
+    }
+}
+
+impl From<DeserializeGameError> for StorageError {
+    fn from(e: DeserializeGameError) -> Self {
+        StorageError::Deserialize(e)
+    }
+}
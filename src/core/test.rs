@@ -1,5 +1,10 @@
 #![allow(unused_imports)]
 use super::*;
+use clock::{Clock, ManualClock};
+use roles::{Investigation, Modifier, Sanity};
+use rules::{DoctorRules, SaveDisclosure, StoppedKiller};
+use stats::{ElectionRecord, KillRecord, NightOutcome, NightRecord, RoleRecord};
+use std::sync::Arc;
 use tokio::join;
 use tokio::time::Duration;
 
@@ -20,20 +25,28 @@ async fn start_print_event_handler(
     })
 }
 
+fn drain_events(event_rx: &mut EventRx<u32>) -> Vec<Event<u32>> {
+    let mut events = Vec::new();
+    while let Ok(event) = event_rx.try_recv() {
+        events.push(event);
+    }
+    events
+}
+
 fn get_players(n: u8) -> HashMap<u32, Role<u32>> {
     let mut players = HashMap::new();
     let role_list = vec![
-        Role::TOWN,         // 1
-        Role::TOWN,         // 2
-        Role::MAFIA,        // 3
-        Role::COP,          // 4
-        Role::DOCTOR,       // 5
-        Role::STRIPPER,     // 6
-        Role::CELEB,        // 7
-        Role::IDIOT(false), // 8
-        Role::SURVIVOR,     // 9
-        Role::AGENT(1),     // 10
-        Role::GUARD(1),     // 11
+        Role::TOWN,              // 1
+        Role::TOWN,              // 2
+        Role::MAFIA,             // 3
+        Role::COP(Sanity::Sane), // 4
+        Role::DOCTOR,            // 5
+        Role::STRIPPER,          // 6
+        Role::CELEB,             // 7
+        Role::IDIOT(false),      // 8
+        Role::SURVIVOR,          // 9
+        Role::AGENT(1),          // 10
+        Role::GUARD(1),          // 11
     ];
     for i in 1..=n {
         players.insert(i as u32, role_list[i as usize - 1]);
@@ -41,6 +54,13 @@ fn get_players(n: u8) -> HashMap<u32, Role<u32>> {
     players
 }
 
+// A town of n players, with the given roles in place of some of them
+fn town_with(n: u32, roles: &[(u32, Role<u32>)]) -> HashMap<u32, Role<u32>> {
+    let mut players: HashMap<u32, Role<u32>> = (1..=n).map(|p| (p, Role::TOWN)).collect();
+    players.extend(roles.iter().copied());
+    players
+}
+
 async fn vote(
     cmd_tx: &CommandTx<u32>,
     voter: u32,
@@ -79,12 +99,35 @@ async fn scheme(
     Interface::send_action(&cmd_tx, action).await
 }
 
-async fn wait() {
-    tokio::time::sleep(Duration::from_millis(250)).await;
+// Spawn a core on a manual clock, so timers only fire when the test advances time
+async fn spawn_core(
+    players: HashMap<u32, Role<u32>>,
+    rules: Rules,
+) -> (
+    JoinHandle<()>,
+    EventRx<u32>,
+    CommandTx<u32>,
+    Arc<ManualClock>,
+) {
+    let clock = ManualClock::new(chrono::Local::now());
+    let (core, event_rx, cmd_tx) = Core::new(0u32, players, rules);
+    let core_join = core.with_clock(clock.clone()).spawn().await;
+    (core_join, event_rx, cmd_tx, clock)
+}
+
+// Advance the clock, then round trip a status request so any timer that came due
+//   has been handled before the test carries on.
+async fn advance(clock: &ManualClock, cmd_tx: &CommandTx<u32>, millis: u64) {
+    clock.advance(Duration::from_millis(millis));
+    let _ = Interface::send_status(cmd_tx).await;
+}
+
+async fn wait(clock: &ManualClock, cmd_tx: &CommandTx<u32>) {
+    advance(clock, cmd_tx, 250).await;
 }
 
-async fn beat() {
-    tokio::time::sleep(Duration::from_millis(15)).await;
+async fn beat(clock: &ManualClock, cmd_tx: &CommandTx<u32>) {
+    advance(clock, cmd_tx, 15).await;
 }
 
 #[tokio::test]
@@ -93,7 +136,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
 
     let players = get_players(7);
 
-    let (core_join, event_rx, cmd_tx) = Core::new_spawned(0, players, Rules::test()).await;
+    let (core_join, event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
 
     let event_handler_join = start_print_event_handler(event_rx).await;
 
@@ -111,7 +154,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
     vote(&cmd_tx, 3, Choice::Player(3)).await?;
     vote(&cmd_tx, 4, Choice::Player(3)).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     // println!("{:#?}", state);
@@ -132,7 +175,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
     target(&cmd_tx, 4, Choice::Player(1)).await?;
     target(&cmd_tx, 5, Choice::Player(5)).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
 
@@ -146,7 +189,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
     vote(&cmd_tx, 6, Choice::Player(1)).await?;
     vote(&cmd_tx, 1, Choice::Player(1)).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::Day);
@@ -161,7 +204,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
 
     Interface::send_action(&cmd_tx, Action::Unvote { voter: 4 }).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::Day);
@@ -169,7 +212,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
     vote(&cmd_tx, 7, Choice::Abstain).await?;
     vote(&cmd_tx, 6, Choice::Abstain).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::Night);
@@ -182,7 +225,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
 
     target(&cmd_tx, 4, Choice::Player(1)).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::Day);
@@ -191,7 +234,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
     vote(&cmd_tx, 6, Choice::Player(2)).await?;
     vote(&cmd_tx, 2, Choice::Player(2)).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
 
@@ -204,7 +247,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
     target(&cmd_tx, 4, Choice::Player(5)).await?;
     target(&cmd_tx, 5, Choice::Player(4)).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::Day);
@@ -212,7 +255,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
     vote(&cmd_tx, 7, Choice::Abstain).await?;
     vote(&cmd_tx, 6, Choice::Abstain).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::Night);
@@ -222,7 +265,7 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
     target(&cmd_tx, 5, Choice::Player(5)).await?;
     scheme(&cmd_tx, 6, Choice::Abstain).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     Interface::send_action(&cmd_tx, Action::Reveal { player: 7 }).await?;
 
@@ -230,12 +273,18 @@ async fn test_targeting() -> Result<(), CoreError<u32>> {
     vote(&cmd_tx, 5, Choice::Player(6)).await?;
     vote(&cmd_tx, 4, Choice::Player(6)).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::End);
 
-    assert!(matches!(state.phase, Phase::End { winner: Team::Town }));
+    assert!(matches!(
+        state.phase,
+        Phase::End {
+            winner: Some(Team::Town),
+            ..
+        }
+    ));
 
     Interface::send_close(&cmd_tx).await;
 
@@ -250,20 +299,20 @@ async fn test_contracts() -> Result<(), CoreError<u32>> {
     // 7-CELEB, 8-IDIOT, 9-SURVIVOR, 10-AGENT(1), 11-GUARD(1)
 
     let players = get_players(11);
-    let (core_join, event_rx, cmd_tx) = Core::new_spawned(0, players, Rules::test()).await;
+    let (core_join, event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
     let event_handler_join = start_print_event_handler(event_rx).await;
 
     Interface::send_action(&cmd_tx, Action::Start).await?;
 
     votes(&cmd_tx, vec![2, 3, 4, 5, 6, 7, 8], Choice::Player(1)).await?;
 
-    beat().await;
+    beat(&clock, &cmd_tx).await;
 
     votes(&cmd_tx, vec![2, 7], Choice::Abstain).await?;
 
     vote(&cmd_tx, 7, Choice::Player(1)).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::Night);
@@ -275,15 +324,15 @@ async fn test_contracts() -> Result<(), CoreError<u32>> {
     scheme(&cmd_tx, 3, Choice::Player(2)).await?;
     target(&cmd_tx, 6, Choice::Player(10)).await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::Day);
 
     votes(&cmd_tx, vec![3, 4, 5, 6, 7], Choice::Player(8)).await?;
 
-    wait().await;
-    wait().await;
+    wait(&clock, &cmd_tx).await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     assert_eq!(state.phase.kind(), PhaseKind::Eclipse);
@@ -297,7 +346,7 @@ async fn test_contracts() -> Result<(), CoreError<u32>> {
     )
     .await?;
 
-    wait().await;
+    wait(&clock, &cmd_tx).await;
 
     let state = Interface::send_status(&cmd_tx).await?;
     println!("{:#?}", state);
@@ -315,14 +364,14 @@ async fn test_serialize() -> Result<(), CoreError<u32>> {
     // Setup a game (in the middle of Election Imminent state)
 
     let players = get_players(11);
-    let (core_join, event_rx, cmd_tx) = Core::new_spawned(0, players, Rules::test()).await;
+    let (core_join, event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
     let event_handler_join = start_print_event_handler(event_rx).await;
 
     Interface::send_action(&cmd_tx, Action::Start).await?;
 
     votes(&cmd_tx, vec![2, 3, 4, 5, 6, 7, 8], Choice::Player(1)).await?;
 
-    beat().await;
+    beat(&clock, &cmd_tx).await;
 
     // Try to serialize the game state
 
@@ -340,3 +389,1487 @@ async fn test_serialize() -> Result<(), CoreError<u32>> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_election_timer_cancel() -> Result<(), CoreError<u32>> {
+    let players = get_players(7);
+    let (core_join, event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
+    let event_handler_join = start_print_event_handler(event_rx).await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+
+    // Quorum is reached, then broken before the election timer fires
+    votes(&cmd_tx, vec![1, 2, 3, 4], Choice::Player(3)).await?;
+    beat(&clock, &cmd_tx).await;
+    Interface::send_action(&cmd_tx, Action::Unvote { voter: 4 }).await?;
+
+    wait(&clock, &cmd_tx).await;
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.phase.kind(), PhaseKind::Day);
+    assert!(state.timer.is_none());
+
+    // A new quorum on a different candidate sets a fresh timer
+    votes(&cmd_tx, vec![4, 5, 6, 7], Choice::Player(1)).await?;
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert!(state.timer.is_some());
+
+    wait(&clock, &cmd_tx).await;
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.phase.kind(), PhaseKind::Night);
+    assert!(!state.players.contains_key(&1));
+
+    Interface::send_close(&cmd_tx).await;
+
+    let _ = join!(core_join, event_handler_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_start_disclosure() -> Result<(), CoreError<u32>> {
+    // 1-TOWN, 2-TOWN, 3-MAFIA, 4-COP, 5-DOCTOR, 6-STRIPPER, 7-CELEB
+    let players = get_players(7);
+    let mut rules = Rules::test();
+    rules.info_rules.start_roles = StartRoles::Mafia;
+    let (core_join, mut event_rx, cmd_tx, _clock) = spawn_core(players, rules).await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::StartRole {
+        player: 3,
+        role: Role::MAFIA,
+        modifiers: vec![],
+        teammates: vec![6],
+    }));
+    assert!(events.contains(&Event::StartRole {
+        player: 4,
+        role: Role::COP(Sanity::Sane),
+        modifiers: vec![],
+        teammates: vec![],
+    }));
+    let Some(Event::Start { disclosure, .. }) = events.first() else {
+        panic!("Expected Start event first");
+    };
+    assert_eq!(
+        *disclosure,
+        StartDisclosure::Mafia {
+            mafia: 2,
+            non_mafia: 5
+        }
+    );
+
+    Interface::send_close(&cmd_tx).await;
+
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_masons() -> Result<(), CoreError<u32>> {
+    let players = town_with(
+        8,
+        &[
+            (1, Role::MASON),
+            (2, Role::MASON),
+            (3, Role::MAFIA),
+            (8, Role::MAFIA),
+        ],
+    );
+    let (core_join, mut event_rx, cmd_tx, _clock) = spawn_core(players, Rules::test()).await;
+
+    // Masons know each other, but not the mafia, and nobody else knows them
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::StartRole {
+        player: 1,
+        role: Role::MASON,
+        modifiers: vec![],
+        teammates: vec![2],
+    }));
+    assert!(events.contains(&Event::StartRole {
+        player: 3,
+        role: Role::MAFIA,
+        modifiers: vec![],
+        teammates: vec![8],
+    }));
+    assert!(events.contains(&Event::StartRole {
+        player: 4,
+        role: Role::TOWN,
+        modifiers: vec![],
+        teammates: vec![],
+    }));
+    assert_eq!(Interface::send_view(&cmd_tx, 2).await?.teammates, vec![1]);
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_info_rules() -> Result<(), CoreError<u32>> {
+    // 1-TOWN, 2-TOWN, 3-MAFIA, 4-COP, 5-DOCTOR, 6-STRIPPER, 7-CELEB
+    let players = get_players(7);
+    let mut rules = Rules::test();
+    rules.info_rules.election = InfoLevel::Mafia;
+    rules.info_rules.kill = InfoLevel::Nothing;
+    rules.info_rules.investigation = InfoLevel::Team;
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+
+    votes(&cmd_tx, vec![1, 2, 4, 5], Choice::Player(3)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Eliminate {
+        player: 3,
+        role: RoleInfo::Mafia(true),
+    }));
+
+    scheme(&cmd_tx, 6, Choice::Player(1)).await?;
+    target(&cmd_tx, 4, Choice::Player(2)).await?;
+    target(&cmd_tx, 5, Choice::Player(5)).await?;
+    target(&cmd_tx, 6, Choice::Abstain).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Eliminate {
+        player: 1,
+        role: RoleInfo::Hidden,
+    }));
+    let result = RoleInfo::Team(Team::Town);
+    assert!(events.contains(&Event::Investigate {
+        actor: 4,
+        target: 2,
+        result,
+    }));
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(
+        state.stats.investigations,
+        vec![Investigation {
+            night: 1,
+            actor: 4,
+            target: 2,
+            result,
+        }]
+    );
+
+    Interface::send_close(&cmd_tx).await;
+
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deadlines() -> Result<(), CoreError<u32>> {
+    // 1-TOWN, 2-TOWN, 3-MAFIA, 4-COP, 5-DOCTOR, 6-STRIPPER, 7-CELEB
+    let players = get_players(7);
+    let mut rules = Rules::test();
+    rules.timer_rules.day_time = Some(Duration::from_secs_f64(0.2));
+    rules.timer_rules.night_time = Some(Duration::from_secs_f64(0.2));
+    rules.timer_rules.day_deadline = DayDeadline::Plurality;
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+
+    // No quorum, but 3 has the most votes when the day runs out
+    votes(&cmd_tx, vec![1, 2], Choice::Player(3)).await?;
+    vote(&cmd_tx, 4, Choice::Player(1)).await?;
+
+    wait(&clock, &cmd_tx).await;
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.phase.kind(), PhaseKind::Night);
+    assert!(!state.players.contains_key(&3));
+
+    // Nobody acts, so dawn comes when the night runs out
+    wait(&clock, &cmd_tx).await;
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.phase.kind(), PhaseKind::Day);
+    assert_eq!(state.day_no, 2);
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::DeadlinePassed {
+        phase: PhaseKind::Day
+    }));
+    assert!(events.contains(&Event::DeadlinePassed {
+        phase: PhaseKind::Night
+    }));
+    assert!(events.contains(&Event::NoNightKill));
+
+    Interface::send_close(&cmd_tx).await;
+
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deadline_pending_election() -> Result<(), CoreError<u32>> {
+    // 1-TOWN, 2-TOWN, 3-MAFIA, 4-COP, 5-DOCTOR, 6-STRIPPER, 7-CELEB
+    let players = get_players(7);
+    let mut rules = Rules::test();
+    rules.timer_rules.day_time = Some(Duration::from_secs_f64(0.2));
+    rules.timer_rules.day_deadline = DayDeadline::NoElimination;
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+    // A quorum forms just before the day runs out, so its election isn't lost
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    advance(&clock, &cmd_tx, 100).await;
+    votes(&cmd_tx, vec![1, 2, 4, 5], Choice::Player(3)).await?;
+    advance(&clock, &cmd_tx, 150).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::DeadlinePassed {
+        phase: PhaseKind::Day
+    }));
+    assert!(events.iter().any(|event| matches!(
+        event,
+        Event::Election {
+            candidate: Choice::Player(3),
+            ..
+        }
+    )));
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.phase.kind(), PhaseKind::Night);
+    assert!(!state.players.contains_key(&3));
+
+    Interface::send_close(&cmd_tx).await;
+
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stats() -> Result<(), CoreError<u32>> {
+    // 1-TOWN, 2-TOWN, 3-MAFIA, 4-COP, 5-DOCTOR, 6-STRIPPER, 7-CELEB
+    let players = get_players(7);
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    votes(&cmd_tx, vec![1, 2, 4, 5], Choice::Player(3)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    scheme(&cmd_tx, 6, Choice::Player(1)).await?;
+    target(&cmd_tx, 4, Choice::Player(2)).await?;
+    target(&cmd_tx, 5, Choice::Player(5)).await?;
+    target(&cmd_tx, 6, Choice::Abstain).await?;
+    wait(&clock, &cmd_tx).await;
+
+    votes(&cmd_tx, vec![2, 4, 5], Choice::Player(6)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let stats = Interface::send_stats(&cmd_tx).await?;
+    assert_eq!(stats.votes.len(), 7);
+    assert_eq!(
+        stats.elections,
+        vec![
+            ElectionRecord {
+                day: 1,
+                candidate: Choice::Player(3),
+                hammer: 5,
+                voters: vec![1, 2, 4, 5],
+                weights: vec![],
+            },
+            ElectionRecord {
+                day: 2,
+                candidate: Choice::Player(6),
+                hammer: 5,
+                voters: vec![2, 4, 5],
+                weights: vec![],
+            },
+        ]
+    );
+    assert_eq!(
+        stats.kills,
+        vec![KillRecord {
+            night: 1,
+            killer: 6,
+            mark: 1
+        }]
+    );
+    assert!(stats.night_actions.contains(&NightRecord {
+        night: 1,
+        actor: 6,
+        role: RoleKind::STRIPPER,
+        target: Choice::Player(1),
+        scheme: true,
+        outcome: NightOutcome::Performed,
+    }));
+    assert!(stats.night_actions.contains(&NightRecord {
+        night: 1,
+        actor: 6,
+        role: RoleKind::STRIPPER,
+        target: Choice::Abstain,
+        scheme: false,
+        outcome: NightOutcome::Abstained,
+    }));
+    assert_eq!(stats.investigations.len(), 1);
+    assert_eq!(stats.days_survived[&3], 1);
+    assert_eq!(stats.days_survived[&1], 1);
+    assert_eq!(stats.days_survived[&6], 2);
+    assert_eq!(stats.days_survived[&2], 2);
+
+    // The same stats come with the end of the game
+    let events = drain_events(&mut event_rx);
+    let Some(Event::End {
+        winner,
+        stats: end_stats,
+        ..
+    }) = events.last()
+    else {
+        panic!("Expected End event last");
+    };
+    assert_eq!(*winner, Some(Team::Town));
+    assert_eq!(**end_stats, stats);
+
+    Interface::send_close(&cmd_tx).await;
+
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rogue_winners() -> Result<(), CoreError<u32>> {
+    // 1-TOWN, 2-TOWN, 3-MAFIA, 4-COP, 5-DOCTOR, 6-STRIPPER,
+    // 7-CELEB, 8-IDIOT, 9-SURVIVOR
+    let players = get_players(9);
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    votes(&cmd_tx, vec![1, 2, 4, 5, 7], Choice::Player(3)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    scheme(&cmd_tx, 6, Choice::Player(1)).await?;
+    target(&cmd_tx, 4, Choice::Player(2)).await?;
+    target(&cmd_tx, 5, Choice::Player(5)).await?;
+    target(&cmd_tx, 6, Choice::Abstain).await?;
+    wait(&clock, &cmd_tx).await;
+
+    votes(&cmd_tx, vec![2, 4, 5, 7], Choice::Player(6)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    // The survivor lived, the idiot was never elected
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(
+        state.phase,
+        Phase::End {
+            winner: Some(Team::Town),
+            rogue_winners: vec![9],
+        }
+    );
+    let events = drain_events(&mut event_rx);
+    assert!(matches!(
+        events.last(),
+        Some(Event::End { winner: Some(Team::Town), rogue_winners, .. }) if *rogue_winners == vec![9]
+    ));
+
+    // Win conditions of each rogue role
+    let alive = get_players(2);
+    assert!(Role::SURVIVOR.is_winner(2, Some(Team::Mafia), &alive));
+    assert!(!Role::SURVIVOR.is_winner(3, Some(Team::Mafia), &alive));
+    assert!(Role::IDIOT(true).is_winner(3, Some(Team::Town), &alive));
+    assert!(!Role::IDIOT(false).is_winner(1, Some(Team::Town), &alive));
+    assert!(Role::GUARD(1).is_winner(3, Some(Team::Mafia), &alive));
+    assert!(!Role::AGENT(1).is_winner(2, Some(Team::Mafia), &alive));
+    assert!(Role::AGENT(3).is_winner(2, Some(Team::Mafia), &alive));
+    assert!(!Role::TOWN.is_winner(1, None, &alive));
+
+    Interface::send_close(&cmd_tx).await;
+
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stopped_killer() -> Result<(), CoreError<u32>> {
+    let definitions = roledefs::from_toml(
+        r#"
+        [[roles]]
+        name = "ESCORT"
+        team = "Town"
+        action = "block"
+        "#,
+    )
+    .unwrap();
+    let escort = roledefs::find(&definitions, "ESCORT").unwrap();
+    for (stopped_killer, killer) in [(StoppedKiller::Fail, None), (StoppedKiller::Pass, Some(5))] {
+        // An even game starts at night. 3 is chosen to kill, but is blocked by 4.
+        //   The stripper already used their action, so the kill can only pass to 5
+        let players = town_with(
+            8,
+            &[
+                (2, Role::STRIPPER),
+                (3, Role::MAFIA),
+                (4, escort),
+                (5, Role::MAFIA),
+            ],
+        );
+        let mut rules = Rules::test();
+        rules.night_rules.stopped_killer = stopped_killer;
+        rules.role_definitions = definitions.clone();
+        let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+        Interface::send_action(&cmd_tx, Action::Start).await?;
+        scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+        target(&cmd_tx, 2, Choice::Player(7)).await?;
+        target(&cmd_tx, 4, Choice::Player(3)).await?;
+        wait(&clock, &cmd_tx).await;
+
+        let events = drain_events(&mut event_rx);
+        assert!(events.contains(&Event::EvidentBlock {
+            blocked: 3,
+            blockers: vec![RoleInfo::Role(escort)],
+        }));
+        let state = Interface::send_status(&cmd_tx).await?;
+        assert_eq!(state.phase.kind(), PhaseKind::Day);
+        let scheme = state
+            .stats
+            .night_actions
+            .iter()
+            .find(|record| record.scheme);
+        let scheme = scheme.expect("The scheme is recorded");
+        match killer {
+            Some(killer) => {
+                assert!(events.contains(&Event::Kill { killer, mark: 1 }));
+                assert!(!state.players.contains_key(&1));
+                assert_eq!(
+                    (scheme.actor, scheme.outcome),
+                    (killer, NightOutcome::Performed)
+                );
+            }
+            None => {
+                assert!(events.contains(&Event::NoNightKill));
+                assert!(state.players.contains_key(&1));
+                assert_eq!((scheme.actor, scheme.outcome), (3, NightOutcome::Blocked));
+            }
+        }
+
+        Interface::send_close(&cmd_tx).await;
+        let _ = join!(core_join);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_vigilante() -> Result<(), CoreError<u32>> {
+    let players = town_with(
+        8,
+        &[
+            (3, Role::MAFIA),
+            (4, Role::VIGILANTE),
+            (5, Role::DOCTOR),
+            (8, Role::MAFIA),
+        ],
+    );
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
+
+    // The mafia kill is saved, the vigilante's shot is not
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    target(&cmd_tx, 4, Choice::Player(8)).await?;
+    target(&cmd_tx, 5, Choice::Player(1)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::EvidentSave {
+        savior: RoleInfo::Role(Role::DOCTOR),
+        mark: 1
+    }));
+    assert!(events.contains(&Event::Shoot { actor: 4, mark: 8 }));
+    assert!(!events.iter().any(|e| matches!(e, Event::Kill { .. })));
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.phase.kind(), PhaseKind::Day);
+    assert!(state.players.contains_key(&1));
+    assert!(!state.players.contains_key(&8));
+    assert_eq!(state.charges[&4], 0);
+    assert_eq!(
+        state.stats.kills,
+        vec![KillRecord {
+            night: 0,
+            killer: 4,
+            mark: 8
+        }]
+    );
+
+    // Out of shots
+    votes(&cmd_tx, vec![1, 2, 5, 6], Choice::Player(7)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert_eq!(
+        target(&cmd_tx, 4, Choice::Player(3)).await,
+        Err(CoreError::NoChargesLeft { actor: 4 })
+    );
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_apparent_alignment() -> Result<(), CoreError<u32>> {
+    let players = town_with(
+        8,
+        &[
+            (2, Role::MILLER),
+            (3, Role::GODFATHER),
+            (4, Role::COP(Sanity::Sane)),
+            (8, Role::MAFIA),
+        ],
+    );
+    let mut rules = Rules::test();
+    rules.info_rules.investigation = InfoLevel::Team;
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+    // The godfather looks innocent
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 3, Choice::Abstain).await?;
+    target(&cmd_tx, 4, Choice::Player(3)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Investigate {
+        actor: 4,
+        target: 3,
+        result: RoleInfo::Team(Team::Town),
+    }));
+
+    // The miller looks guilty
+    votes(&cmd_tx, vec![1, 2, 5, 6, 7], Choice::Player(5)).await?;
+    wait(&clock, &cmd_tx).await;
+    scheme(&cmd_tx, 3, Choice::Abstain).await?;
+    target(&cmd_tx, 4, Choice::Player(2)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Investigate {
+        actor: 4,
+        target: 2,
+        result: RoleInfo::Team(Team::Mafia),
+    }));
+
+    assert_eq!(Role::<u32>::GODFATHER.apparent_role(), Role::TOWN);
+    assert_eq!(Role::<u32>::MILLER.apparent_team(), Team::Mafia);
+    assert_eq!(Role::<u32>::MILLER.team(), Team::Town);
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cop_sanity() -> Result<(), CoreError<u32>> {
+    let players = town_with(
+        8,
+        &[
+            (3, Role::MAFIA),
+            (4, Role::COP(Sanity::Insane)),
+            (8, Role::MAFIA),
+        ],
+    );
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
+
+    // The cop isn't told their sanity, and sees the mafia as innocent
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 3, Choice::Abstain).await?;
+    target(&cmd_tx, 4, Choice::Player(3)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::StartRole {
+        player: 4,
+        role: Role::COP(Sanity::Sane),
+        modifiers: vec![],
+        teammates: vec![],
+    }));
+    assert!(events.contains(&Event::Investigate {
+        actor: 4,
+        target: 3,
+        result: RoleInfo::Role(Role::TOWN),
+    }));
+
+    // The true sanity is kept for the end of the game
+    let stats = Interface::send_stats(&cmd_tx).await?;
+    assert_eq!(
+        stats.role_history[&4],
+        vec![RoleRecord {
+            role: Role::COP(Sanity::Insane),
+            modifiers: vec![]
+        }]
+    );
+
+    assert_eq!(Sanity::Naive.skew(Role::<u32>::MAFIA), Role::TOWN);
+    assert_eq!(Sanity::Naive.skew(Role::<u32>::DOCTOR), Role::DOCTOR);
+    assert_eq!(Sanity::Paranoid.skew(Role::<u32>::DOCTOR), Role::MAFIA);
+    assert_eq!(
+        Sanity::Insane.skew(Role::<u32>::MILLER.apparent_role()),
+        Role::TOWN
+    );
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_visits() -> Result<(), CoreError<u32>> {
+    let players = town_with(
+        8,
+        &[
+            (3, Role::MAFIA),
+            (4, Role::TRACKER),
+            (5, Role::WATCHER),
+            (6, Role::DOCTOR),
+            (8, Role::MAFIA),
+        ],
+    );
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
+
+    // The tracker follows the killer, the watcher sits on the mark
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    target(&cmd_tx, 4, Choice::Player(3)).await?;
+    target(&cmd_tx, 5, Choice::Player(1)).await?;
+    target(&cmd_tx, 6, Choice::Player(1)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Track {
+        actor: 4,
+        target: 3,
+        visited: vec![1],
+    }));
+    assert!(events.contains(&Event::Watch {
+        actor: 5,
+        target: 1,
+        visitors: vec![3, 6],
+    }));
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_doctor_rules() -> Result<(), CoreError<u32>> {
+    let players = town_with(8, &[(3, Role::MAFIA), (5, Role::DOCTOR), (8, Role::MAFIA)]);
+    let mut rules = Rules::test();
+    rules.doctor_rules = DoctorRules {
+        allow_self_save: false,
+        allow_consecutive_saves: false,
+        save_disclosure: SaveDisclosure::Saved,
+    };
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+    // No self saves, and only the saved player hears about it
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    assert_eq!(
+        target(&cmd_tx, 5, Choice::Player(5)).await,
+        Err(CoreError::IllegalSaveTarget {
+            actor: 5,
+            target: 5
+        })
+    );
+    target(&cmd_tx, 5, Choice::Player(1)).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::PrivateSave {
+        savior: RoleInfo::Role(Role::DOCTOR),
+        mark: 1
+    }));
+    assert!(!events
+        .iter()
+        .any(|e| matches!(e, Event::EvidentSave { .. })));
+
+    // No saving the same player two nights in a row
+    votes(&cmd_tx, vec![1, 2, 4, 5, 6], Choice::Player(7)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert_eq!(
+        target(&cmd_tx, 5, Choice::Player(1)).await,
+        Err(CoreError::IllegalSaveTarget {
+            actor: 5,
+            target: 1
+        })
+    );
+    target(&cmd_tx, 5, Choice::Player(2)).await?;
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_modifiers() -> Result<(), CoreError<u32>> {
+    let players = town_with(
+        8,
+        &[
+            (3, Role::MAFIA),
+            (4, Role::COP(Sanity::Sane)),
+            (6, Role::STRIPPER),
+            (8, Role::MAFIA),
+        ],
+    );
+    let modifiers = HashMap::from([
+        (1, vec![Modifier::Bulletproof]),
+        (4, vec![Modifier::OneShot(false)]),
+        (7, vec![Modifier::BlockImmune, Modifier::InvestigateImmune]),
+    ]);
+    let clock = ManualClock::new(chrono::Local::now());
+    let (core, mut event_rx, cmd_tx) = Core::new(0u32, players, Rules::test());
+    let core_join = core
+        .with_modifiers(modifiers)
+        .with_clock(clock.clone())
+        .spawn()
+        .await;
+
+    // The kill is absorbed, the block and the investigation have no effect
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    target(&cmd_tx, 4, Choice::Player(7)).await?;
+    target(&cmd_tx, 6, Choice::Player(7)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::StartRole {
+        player: 1,
+        role: Role::TOWN,
+        modifiers: vec![Modifier::Bulletproof],
+        teammates: vec![],
+    }));
+    assert!(events.contains(&Event::EvidentBulletproof { mark: 1 }));
+    assert!(!events.iter().any(|e| matches!(e, Event::Kill { .. })));
+    assert!(!events.iter().any(|e| matches!(e, Event::Block { .. })));
+    assert!(events.contains(&Event::Investigate {
+        actor: 4,
+        target: 7,
+        result: RoleInfo::Hidden,
+    }));
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert!(state.players.contains_key(&1));
+    assert_eq!(state.modifiers[&1], vec![]);
+    assert_eq!(state.modifiers[&4], vec![Modifier::OneShot(true)]);
+    assert!(state.stats.blocks.is_empty());
+    assert_eq!(
+        state.stats.role_history[&1],
+        vec![RoleRecord {
+            role: Role::TOWN,
+            modifiers: vec![Modifier::Bulletproof]
+        }]
+    );
+
+    // The vest is gone and the cop's one investigation is used
+    votes(&cmd_tx, vec![1, 2, 4, 5, 7], Choice::Player(2)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert_eq!(
+        target(&cmd_tx, 4, Choice::Player(3)).await,
+        Err(CoreError::ExpectedTargetingRole {
+            role: RoleKind::COP
+        })
+    );
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    target(&cmd_tx, 6, Choice::Abstain).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Kill { killer: 3, mark: 1 }));
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oneshot_faction_kill() -> Result<(), CoreError<u32>> {
+    let players = town_with(8, &[(3, Role::MAFIA), (6, Role::STRIPPER)]);
+    let modifiers = HashMap::from([(6, vec![Modifier::OneShot(false)])]);
+    let clock = ManualClock::new(chrono::Local::now());
+    let (core, mut event_rx, cmd_tx) = Core::new(0u32, players, Rules::test());
+    let core_join = core
+        .with_modifiers(modifiers)
+        .with_clock(clock.clone())
+        .spawn()
+        .await;
+
+    // Abstaining and doing the faction's kill keeps the stripper's one block
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    target(&cmd_tx, 6, Choice::Abstain).await?;
+    scheme(&cmd_tx, 6, Choice::Player(1)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Kill { killer: 6, mark: 1 }));
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.modifiers[&6], vec![Modifier::OneShot(false)]);
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_charges() -> Result<(), CoreError<u32>> {
+    let players = town_with(
+        8,
+        &[
+            (3, Role::MAFIA),
+            (4, Role::COP(Sanity::Sane)),
+            (8, Role::MAFIA),
+        ],
+    );
+    let clock = ManualClock::new(chrono::Local::now());
+    let (core, _event_rx, cmd_tx) = Core::new(0u32, players, Rules::test());
+    let core_join = core
+        .with_charges(HashMap::from([(4, 1)]))
+        .with_clock(clock.clone())
+        .spawn()
+        .await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    let view = Interface::send_view(&cmd_tx, 4).await?;
+    assert_eq!(view.charges, Some(1));
+    assert_eq!(view.phase, PhaseKind::Night);
+    assert_eq!(Interface::send_view(&cmd_tx, 3).await?.teammates, vec![8]);
+
+    // Changing the target doesn't spend another charge, abstaining gives it back
+    target(&cmd_tx, 4, Choice::Player(5)).await?;
+    assert_eq!(Interface::send_view(&cmd_tx, 4).await?.charges, Some(0));
+    target(&cmd_tx, 4, Choice::Player(6)).await?;
+    assert_eq!(Interface::send_view(&cmd_tx, 4).await?.charges, Some(0));
+    target(&cmd_tx, 4, Choice::Abstain).await?;
+    assert_eq!(Interface::send_view(&cmd_tx, 4).await?.charges, Some(1));
+    target(&cmd_tx, 4, Choice::Player(6)).await?;
+    scheme(&cmd_tx, 3, Choice::Abstain).await?;
+    wait(&clock, &cmd_tx).await;
+
+    // Out of charges, and dawn doesn't wait on the cop
+    votes(&cmd_tx, vec![1, 2, 4, 5, 6], Choice::Player(7)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert_eq!(
+        target(&cmd_tx, 4, Choice::Player(3)).await,
+        Err(CoreError::NoChargesLeft { actor: 4 })
+    );
+    scheme(&cmd_tx, 3, Choice::Abstain).await?;
+    wait(&clock, &cmd_tx).await;
+    let view = Interface::send_view(&cmd_tx, 4).await?;
+    assert_eq!(view.phase, PhaseKind::Day);
+    assert_eq!(view.charges, Some(0));
+
+    assert_eq!(
+        Interface::send_view(&cmd_tx, 7).await,
+        Err(CoreError::InvalidPlayer { player: 7 })
+    );
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_custom_roles() -> Result<(), CoreError<u32>> {
+    let definitions = roledefs::from_toml(
+        r#"
+        [[roles]]
+        name = "SEER"
+        team = "Town"
+        action = "investigate"
+
+        [[roles]]
+        name = "BOUNCER"
+        team = "Town"
+        action = "block"
+        modifiers = ["bulletproof"]
+        "#,
+    )
+    .unwrap();
+    let seer = roledefs::find(&definitions, "SEER").unwrap();
+    let bouncer = roledefs::find(&definitions, "BOUNCER").unwrap();
+    let players = town_with(
+        8,
+        &[(3, Role::MAFIA), (4, seer), (5, bouncer), (8, Role::MAFIA)],
+    );
+    let modifiers = roledefs::modifiers(&definitions, &players);
+    assert_eq!(modifiers, HashMap::from([(5, vec![Modifier::Bulletproof])]));
+    let mut rules = Rules::test();
+    rules.role_definitions = definitions;
+    let clock = ManualClock::new(chrono::Local::now());
+    let (core, mut event_rx, cmd_tx) = Core::new(0u32, players, rules.clone());
+    let core_join = core.with_clock(clock.clone()).spawn().await;
+
+    // The bouncer blocks the killer, the seer investigates like a cop
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    target(&cmd_tx, 4, Choice::Player(8)).await?;
+    target(&cmd_tx, 5, Choice::Player(3)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Block {
+        actor: 5,
+        target: 3
+    }));
+    assert!(events.contains(&Event::Investigate {
+        actor: 4,
+        target: 8,
+        result: RoleInfo::new(Role::MAFIA, rules.info_rules.investigation),
+    }));
+    assert!(events.contains(&Event::NoNightKill));
+
+    let stats = Interface::send_stats(&cmd_tx).await?;
+    assert!(stats.night_actions.contains(&NightRecord {
+        night: 0,
+        actor: 5,
+        role: RoleKind::CUSTOM,
+        target: Choice::Player(3),
+        scheme: false,
+        outcome: NightOutcome::Performed,
+    }));
+    // The bouncer's modifiers come with the role
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.modifiers[&5], vec![Modifier::Bulletproof]);
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_script_roles() -> Result<(), CoreError<u32>> {
+    let definitions = roledefs::from_toml(
+        r#"
+        [[roles]]
+        name = "JAILER"
+        team = "Town"
+        action = "script"
+        priority = 3
+        script = """
+            [block(target), save(target), message(target, "You were jailed")]
+        """
+
+        [[roles]]
+        name = "LOOPER"
+        team = "Town"
+        action = "script"
+        script = "loop {}"
+        "#,
+    )
+    .unwrap();
+    let players = town_with(
+        8,
+        &[
+            (3, Role::MAFIA),
+            (4, roledefs::find(&definitions, "JAILER").unwrap()),
+            (5, roledefs::find(&definitions, "LOOPER").unwrap()),
+            (8, Role::MAFIA),
+        ],
+    );
+    let mut rules = Rules::test();
+    rules.role_definitions = definitions;
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+    // The jailer saves the mafia's mark, and the looper's script runs out of steps
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    target(&cmd_tx, 4, Choice::Player(1)).await?;
+    target(&cmd_tx, 5, Choice::Player(2)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::ScriptMessage {
+        player: 1,
+        text: "You were jailed".to_string(),
+    }));
+    assert!(events.contains(&Event::Save {
+        actor: 4,
+        target: 1
+    }));
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, Event::ScriptFailed { actor: 5, .. })));
+    assert!(events.contains(&Event::NoNightKill));
+
+    let stats = Interface::send_stats(&cmd_tx).await?;
+    assert!(stats.night_actions.contains(&NightRecord {
+        night: 0,
+        actor: 5,
+        role: RoleKind::CUSTOM,
+        target: Choice::Player(2),
+        scheme: false,
+        outcome: NightOutcome::Failed,
+    }));
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factions() -> Result<(), CoreError<u32>> {
+    let definitions = roledefs::from_toml(
+        r#"
+        [[roles]]
+        name = "SERIAL_KILLER"
+        team = "Killer"
+        "#,
+    )
+    .unwrap();
+    let players = town_with(
+        8,
+        &[
+            (3, Role::MAFIA),
+            (6, roledefs::find(&definitions, "SERIAL_KILLER").unwrap()),
+            (8, Role::MAFIA),
+        ],
+    );
+    let mut rules = Rules::test();
+    rules.role_definitions = definitions;
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+    // Each faction only knows its own members
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::StartRole {
+        player: 3,
+        role: Role::MAFIA,
+        modifiers: vec![],
+        teammates: vec![8],
+    }));
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, Event::StartRole { player: 6, teammates, .. } if teammates.is_empty())));
+
+    // Dawn waits for every faction to scheme, then both kills go ahead
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(!drain_events(&mut event_rx).contains(&Event::Dawn));
+    scheme(&cmd_tx, 6, Choice::Player(3)).await?;
+    wait(&clock, &cmd_tx).await;
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Kill { killer: 3, mark: 1 }));
+    assert!(events.contains(&Event::Kill { killer: 6, mark: 3 }));
+
+    // The town can't win while the killer is alive
+    votes(&cmd_tx, vec![2, 4, 5, 7], Choice::Player(8)).await?;
+    wait(&clock, &cmd_tx).await;
+    let events = drain_events(&mut event_rx);
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, Event::Eliminate { player: 8, .. })));
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, Event::End { .. })));
+
+    // The last faction schemes alone
+    scheme(&cmd_tx, 6, Choice::Player(2)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(drain_events(&mut event_rx).contains(&Event::Kill { killer: 6, mark: 2 }));
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bulletproof_factions() -> Result<(), CoreError<u32>> {
+    let definitions = roledefs::from_toml(
+        r#"
+        [[roles]]
+        name = "SERIAL_KILLER"
+        team = "Killer"
+        "#,
+    )
+    .unwrap();
+    let players = town_with(
+        8,
+        &[
+            (3, Role::MAFIA),
+            (6, roledefs::find(&definitions, "SERIAL_KILLER").unwrap()),
+        ],
+    );
+    let mut rules = Rules::test();
+    rules.role_definitions = definitions;
+    let clock = ManualClock::new(chrono::Local::now());
+    let (core, mut event_rx, cmd_tx) = Core::new(0u32, players, rules);
+    let core_join = core
+        .with_modifiers(HashMap::from([(1, vec![Modifier::Bulletproof])]))
+        .with_clock(clock.clone())
+        .spawn()
+        .await;
+
+    // Bulletproof stops the first faction's kill but not the second
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    scheme(&cmd_tx, 6, Choice::Player(1)).await?;
+    wait(&clock, &cmd_tx).await;
+
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::EvidentBulletproof { mark: 1 }));
+    assert!(events.contains(&Event::Kill { killer: 6, mark: 1 }));
+    assert!(!events.contains(&Event::Kill { killer: 3, mark: 1 }));
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert!(!state.players.contains_key(&1));
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_faction_draws() -> Result<(), CoreError<u32>> {
+    let definitions = roledefs::from_toml(
+        r#"
+        [[roles]]
+        name = "SERIAL_KILLER"
+        team = "Killer"
+
+        [[roles]]
+        name = "GODFATHER"
+        team = "Family"
+        "#,
+    )
+    .unwrap();
+    let killer = roledefs::find(&definitions, "SERIAL_KILLER").unwrap();
+    let family = roledefs::find(&definitions, "GODFATHER").unwrap();
+    let mut rules = Rules::test();
+    rules.role_definitions = definitions;
+
+    // Two lone killers facing off can never win
+    let players = HashMap::from([
+        (1, Role::MAFIA),
+        (2, killer),
+        (3, Role::TOWN),
+        (4, Role::TOWN),
+    ]);
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules.clone()).await;
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 1, Choice::Player(3)).await?;
+    scheme(&cmd_tx, 2, Choice::Player(4)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(matches!(
+        drain_events(&mut event_rx).last(),
+        Some(Event::End { winner: None, alive, .. }) if *alive == vec![1, 2]
+    ));
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    // Nobody wins when nobody is left
+    let players = HashMap::from([(1, Role::MAFIA), (2, family), (3, killer)]);
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    votes(&cmd_tx, vec![1, 2], Choice::Abstain).await?;
+    wait(&clock, &cmd_tx).await;
+    scheme(&cmd_tx, 1, Choice::Player(2)).await?;
+    scheme(&cmd_tx, 2, Choice::Player(3)).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(matches!(
+        drain_events(&mut event_rx).last(),
+        Some(Event::End { winner: None, alive, .. }) if alive.is_empty()
+    ));
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scheme_decisions() -> Result<(), CoreError<u32>> {
+    let players = town_with(8, &[(3, Role::MAFIA), (5, Role::MAFIA), (8, Role::MAFIA)]);
+    let mut rules = Rules::test();
+    rules.night_rules.scheme_decision = SchemeDecision::Majority;
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+    // The mafia see each pick, and dawn waits for a majority
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    scheme(&cmd_tx, 5, Choice::Player(2)).await?;
+    wait(&clock, &cmd_tx).await;
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::SchemePicks {
+        team: Team::Mafia,
+        picks: vec![(3, Choice::Player(1)), (5, Choice::Player(2))],
+    }));
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, Event::Scheme { .. } | Event::Dawn)));
+
+    scheme(&cmd_tx, 8, Choice::Player(2)).await?;
+    wait(&clock, &cmd_tx).await;
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Scheme {
+        team: Team::Mafia,
+        actor: 5,
+        mark: Choice::Player(2)
+    }));
+    assert!(events.contains(&Event::Kill { killer: 5, mark: 2 }));
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    // The mafia can hand the decision to any of its members
+    let players = town_with(8, &[(3, Role::MAFIA), (5, Role::MAFIA), (8, Role::MAFIA)]);
+    let mut rules = Rules::test();
+    rules.night_rules.scheme_decision = SchemeDecision::Designated;
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, rules).await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    let designate = |actor, killer| Action::Designate { actor, killer };
+    assert_eq!(
+        Interface::send_action(&cmd_tx, designate(5, 1)).await,
+        Err(CoreError::InvalidOption {
+            actual: 1,
+            options: vec![3, 5, 8]
+        })
+    );
+    Interface::send_action(&cmd_tx, designate(5, 8)).await?;
+    scheme(&cmd_tx, 3, Choice::Player(1)).await?;
+    wait(&clock, &cmd_tx).await;
+    let events = drain_events(&mut event_rx);
+    assert!(events.contains(&Event::Designate {
+        team: Team::Mafia,
+        killer: 8
+    }));
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, Event::Scheme { .. } | Event::Dawn)));
+
+    scheme(&cmd_tx, 8, Choice::Player(2)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(drain_events(&mut event_rx).contains(&Event::Kill { killer: 8, mark: 2 }));
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    // Designated leaves it to the first member, and ties are broken once everyone has picked
+    let members = [3, 5, 8];
+    let picks = [
+        (8, Choice::Player(1)),
+        (5, Choice::Player(2)),
+        (3, Choice::Player(4)),
+    ];
+    let mut rules = NightRules::default();
+    let decide = Core::<u32, u32>::decide_scheme;
+    assert_eq!(
+        decide(&rules, &members, None, &picks[..2], false),
+        Some(picks[1])
+    );
+    rules.scheme_decision = SchemeDecision::Designated;
+    assert_eq!(
+        decide(&rules, &members, None, &picks, false),
+        Some(picks[2])
+    );
+    assert_eq!(decide(&rules, &members, None, &picks[..2], false), None);
+    assert_eq!(
+        decide(&rules, &members, Some(5), &picks, false),
+        Some(picks[1])
+    );
+    rules.scheme_decision = SchemeDecision::Majority;
+    assert_eq!(decide(&rules, &members, None, &picks[..2], false), None);
+    assert_eq!(
+        decide(&rules, &members, None, &picks, false),
+        Some((8, Choice::Abstain))
+    );
+    rules.scheme_tie_break = SchemeTieBreak::FirstPick;
+    assert_eq!(
+        decide(&rules, &members, None, &picks, false),
+        Some(picks[0])
+    );
+    // The night ended with one pick in
+    assert_eq!(
+        decide(&rules, &members, None, &picks[1..2], true),
+        Some(picks[1])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_vote_weights() -> Result<(), CoreError<u32>> {
+    let players = town_with(7, &[(1, Role::MAYOR), (6, Role::THIEF), (7, Role::MAFIA)]);
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
+
+    // A revealed mayor's vote counts double, and raises the threshold with it
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    votes(&cmd_tx, vec![1, 2, 3], Choice::Player(7)).await?;
+    Interface::send_action(&cmd_tx, Action::Reveal { player: 1 }).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(!drain_events(&mut event_rx)
+        .iter()
+        .any(|event| matches!(event, Event::Election { .. })));
+    vote(&cmd_tx, 4, Choice::Player(7)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(drain_events(&mut event_rx).contains(&Event::Election {
+        candidate: Choice::Player(7),
+        hammer: 4,
+        voters: vec![(1, 2), (2, 1), (3, 1), (4, 1)],
+    }));
+
+    // The thief takes a vote for the next day
+    target(&cmd_tx, 6, Choice::Player(2)).await?;
+    scheme(&cmd_tx, 6, Choice::Abstain).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(drain_events(&mut event_rx).contains(&Event::Steal {
+        actor: 6,
+        target: 2
+    }));
+    votes(&cmd_tx, vec![2, 5, 6], Choice::Player(3)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(!drain_events(&mut event_rx)
+        .iter()
+        .any(|event| matches!(event, Event::Election { .. })));
+    vote(&cmd_tx, 4, Choice::Player(3)).await?;
+    wait(&clock, &cmd_tx).await;
+    assert!(drain_events(&mut event_rx).contains(&Event::Election {
+        candidate: Choice::Player(3),
+        hammer: 4,
+        voters: vec![(2, 0), (4, 1), (5, 1), (6, 2)],
+    }));
+
+    let stats = Interface::send_stats(&cmd_tx).await?;
+    assert_eq!(stats.elections[0].weights, vec![(1, 2)]);
+    assert_eq!(stats.elections[1].weights, vec![(2, 0), (6, 2)]);
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_storage_restore() -> Result<(), CoreError<u32>> {
+    let dir = std::env::temp_dir().join(format!("mafiabot_test_{}", std::process::id()));
+    let store = GameStore::new(&dir);
+
+    let players = get_players(7);
+    let clock = ManualClock::new(chrono::Local::now());
+    let (core, event_rx, cmd_tx) = Core::new(5u32, players, Rules::test());
+    let core_join = core
+        .with_clock(clock.clone())
+        .with_storage(store.clone())
+        .spawn()
+        .await;
+    let event_handler_join = start_print_event_handler(event_rx).await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    votes(&cmd_tx, vec![1, 2, 4, 5], Choice::Player(3)).await?;
+
+    // Shut down while the election is imminent
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(core_join, event_handler_join);
+
+    // Downtime is longer than the election timer
+    clock.advance(Duration::from_millis(250));
+
+    // A snapshot cut off mid-write doesn't stop the others being restored
+    std::fs::write(dir.join("6.json"), "{\"game_id\": \"6\", \"st").unwrap();
+    assert_eq!(store.load_all().expect("Load").len(), 1);
+
+    let restored = store
+        .restore_all::<u32, u32>(clock.clone())
+        .await
+        .expect("Restore");
+    assert_eq!(restored.len(), 1);
+    let game = restored.into_iter().next().unwrap();
+    assert_eq!(game.game_id, 5);
+    let cmd_tx = game.cmd_tx;
+    let event_handler_join = start_print_event_handler(game.event_rx).await;
+
+    // Timer resumes with the time it had left
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.phase.kind(), PhaseKind::Day);
+    let timer = state.timer.expect("Election timer");
+    assert_eq!(timer.remaining(clock.now()), Duration::from_millis(200));
+
+    wait(&clock, &cmd_tx).await;
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.phase.kind(), PhaseKind::Night);
+    assert!(!state.players.contains_key(&3));
+
+    Interface::send_close(&cmd_tx).await;
+    let _ = join!(game.join, event_handler_join);
+
+    let _ = std::fs::remove_dir_all(dir);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replay() -> Result<(), CoreError<u32>> {
+    // 1-TOWN, 2-TOWN, 3-MAFIA, 4-COP, 5-DOCTOR, 6-STRIPPER, 7-CELEB
+    let players = get_players(7);
+    let (core_join, mut event_rx, cmd_tx, clock) = spawn_core(players, Rules::test()).await;
+
+    Interface::send_action(&cmd_tx, Action::Start).await?;
+    votes(&cmd_tx, vec![1, 2, 4, 5], Choice::Player(3)).await?;
+    // Rejected actions are not logged
+    assert!(scheme(&cmd_tx, 6, Choice::Player(1)).await.is_err());
+
+    wait(&clock, &cmd_tx).await;
+
+    scheme(&cmd_tx, 6, Choice::Player(1)).await?;
+    target(&cmd_tx, 4, Choice::Player(2)).await?;
+    target(&cmd_tx, 5, Choice::Player(1)).await?;
+    target(&cmd_tx, 6, Choice::Abstain).await?;
+
+    wait(&clock, &cmd_tx).await;
+
+    let state = Interface::send_status(&cmd_tx).await?;
+    assert_eq!(state.phase.kind(), PhaseKind::Day);
+    let events = drain_events(&mut event_rx);
+
+    let log = Interface::send_log(&cmd_tx).await?;
+    let timer_actions: Vec<_> = log
+        .entries
+        .iter()
+        .filter(|entry| entry.from_timer)
+        .map(|entry| entry.action.clone())
+        .collect();
+    assert_eq!(
+        timer_actions,
+        vec![
+            Action::Elect {
+                candidate: Choice::Player(3),
+                hammer: 5
+            },
+            Action::Dawn
+        ]
+    );
+
+    let (replay_state, replay_events) = log.replay(0u32).await?;
+    assert_eq!(replay_state, state);
+    assert_eq!(replay_events, events);
+
+    Interface::send_close(&cmd_tx).await;
+
+    let _ = join!(core_join);
+
+    Ok(())
+}
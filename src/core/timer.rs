@@ -7,21 +7,17 @@ use chrono;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use std::hash::Hash;
+use tokio::time::Duration;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Timer<PID: Eq + Hash> {
     pub end_time: chrono::DateTime<chrono::Local>,
     pub data: Action<PID>,
 }
 
 impl<PID: ID> Timer<PID> {
-    // Or just return an action?
-    pub async fn check(&self) -> Option<Action<PID>> {
-        let now = chrono::Local::now();
-        if now > self.end_time {
-            Some(self.data.clone())
-        } else {
-            None
-        }
+    // Time left until the timer fires. Zero if the end time has already passed.
+    pub fn remaining(&self, now: chrono::DateTime<chrono::Local>) -> Duration {
+        (self.end_time - now).to_std().unwrap_or(Duration::ZERO)
     }
 }
@@ -1,7 +1,9 @@
 use std::collections::HashMap;
 use std::env;
+use std::path::Path;
 
 use crate::controller::Controller;
+use crate::core::storage::GameStore;
 
 use serenity::async_trait;
 use serenity::client::{Client, Context, EventHandler};
@@ -11,6 +13,9 @@ use serenity::model::gateway::{GatewayIntents, Ready};
 use serenity::model::id::GuildId;
 use serenity::prelude::TypeMapKey;
 
+// Each guild keeps its games in its own directory under this one
+const GAMES_DIR: &str = "data/games";
+
 struct Server {
     controller: HashMap<GuildId, Controller>,
 }
@@ -29,12 +34,17 @@ impl Handler {
         _is_new: Option<bool>,
     ) {
         {
-            // Add a controller for this guild
+            // Add a controller for this guild, resuming any games it had running.
+            //   Guilds show up again after ready, so only the first time.
             let mut data = ctx.data.write().await;
             let server = data.get_mut::<Server>().unwrap();
-            server
-                .controller
-                .insert(guild, Controller::new(guild, ctx.http.clone()));
+            if server.controller.contains_key(&guild) {
+                return;
+            }
+            let store = GameStore::new(Path::new(GAMES_DIR).join(guild.to_string()));
+            let mut controller = Controller::new(guild, ctx.http.clone(), store);
+            controller.restore_games(ctx.http.clone()).await;
+            server.controller.insert(guild, controller);
         }
     }
 }